@@ -0,0 +1,97 @@
+use image::{Rgba, RgbaImage};
+use nalgebra::Vector2;
+use notan::draw::*;
+use notan::prelude::*;
+use serde::{Deserialize, Serialize};
+use strum::Display;
+use strum_macros::EnumIter;
+
+/// What is shown behind transparent parts of the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumIter, Display)]
+pub enum BackgroundMode {
+    Solid,
+    Checkerboard,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackgroundSettings {
+    pub mode: BackgroundMode,
+    /// Color of the window, also around the checkerboard
+    pub color: [u8; 3],
+    /// Edge length of a checker square in screen pixels
+    pub checker_size: u32,
+    pub checker_colors: [[u8; 3]; 2],
+}
+
+impl Default for BackgroundSettings {
+    fn default() -> Self {
+        Self {
+            mode: BackgroundMode::Solid,
+            color: [51, 51, 51],
+            checker_size: 16,
+            checker_colors: [[153, 153, 153], [102, 102, 102]],
+        }
+    }
+}
+
+impl BackgroundSettings {
+    pub fn clear_color(&self) -> Color {
+        Color::from_bytes(self.color[0], self.color[1], self.color[2], 255)
+    }
+
+    /// Switch between a solid color and the checkerboard
+    pub fn toggle(&mut self) {
+        self.mode = match self.mode {
+            BackgroundMode::Solid => BackgroundMode::Checkerboard,
+            BackgroundMode::Checkerboard => BackgroundMode::Solid,
+        };
+    }
+}
+
+/// One tile of the checker pattern: two by two squares of `size` pixels
+pub fn checker_tile(size: u32, colors: [[u8; 3]; 2]) -> RgbaImage {
+    let size = size.max(1);
+    RgbaImage::from_fn(size * 2, size * 2, |x, y| {
+        let [r, g, b] = colors[((x / size + y / size) % 2) as usize];
+        Rgba([r, g, b, 255])
+    })
+}
+
+/// The checker pattern texture, rebuilt when its size or colors change
+#[derive(Debug, Default)]
+pub struct Checkerboard {
+    texture: Option<Texture>,
+    built_for: Option<(u32, [[u8; 3]; 2])>,
+}
+
+impl Checkerboard {
+    /// Fill the screen rectangle at `position` with the pattern, which stays put
+    /// relative to the rectangle so it moves along when panning
+    pub fn draw(
+        &mut self,
+        draw: &mut Draw,
+        gfx: &mut Graphics,
+        settings: &BackgroundSettings,
+        position: Vector2<f32>,
+        size: Vector2<f32>,
+    ) {
+        let key = (settings.checker_size, settings.checker_colors);
+        if self.built_for != Some(key) {
+            let tile = checker_tile(settings.checker_size, settings.checker_colors);
+            self.texture = gfx
+                .create_texture()
+                .from_bytes(&tile, tile.width() as i32, tile.height() as i32)
+                .with_format(TextureFormat::SRgba8)
+                .with_filter(TextureFilter::Nearest, TextureFilter::Nearest)
+                .build()
+                .ok();
+            self.built_for = Some(key);
+        }
+        if let Some(texture) = &self.texture {
+            draw.pattern(texture)
+                .blend_mode(BlendMode::NORMAL)
+                .translate(position.x, position.y)
+                .size(size.x, size.y);
+        }
+    }
+}
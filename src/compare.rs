@@ -0,0 +1,589 @@
+use anyhow::{anyhow, Result};
+use image::RgbaImage;
+use log::{debug, error};
+use nalgebra::Vector2;
+use notan::draw::*;
+use notan::prelude::{Color, Graphics, Texture, TextureFilter};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use strum::Display;
+use strum_macros::EnumIter;
+
+use crate::diff::{self, Metric, Metrics};
+use crate::utils::{open_image, ImageExt};
+
+/// Space between the compared images, in pixels of the first image
+const GAP: f32 = 8.0;
+
+/// How the two images are arranged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, Display)]
+pub enum CompareMode {
+    #[strum(serialize = "Side by side")]
+    SideBySide,
+    Stacked,
+    /// The second image to the right of a movable split
+    Wipe,
+    /// Either image, switched with a hotkey
+    Flicker,
+    Difference,
+    /// Pixels that differ at all, or by more than a threshold
+    #[strum(serialize = "Changed pixels")]
+    ChangedPixels,
+}
+
+impl CompareMode {
+    /// Whether the images are drawn on top of each other
+    pub fn is_overlay(&self) -> bool {
+        !matches!(self, CompareMode::SideBySide | CompareMode::Stacked)
+    }
+}
+
+impl Default for CompareMode {
+    fn default() -> Self {
+        CompareMode::SideBySide
+    }
+}
+
+/// The point at which images of different sizes line up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, Display)]
+pub enum Anchor {
+    #[strum(serialize = "Top left")]
+    TopLeft,
+    Top,
+    #[strum(serialize = "Top right")]
+    TopRight,
+    Left,
+    Center,
+    Right,
+    #[strum(serialize = "Bottom left")]
+    BottomLeft,
+    Bottom,
+    #[strum(serialize = "Bottom right")]
+    BottomRight,
+}
+
+impl Default for Anchor {
+    fn default() -> Self {
+        Anchor::TopLeft
+    }
+}
+
+impl Anchor {
+    /// Position of the anchor relative to the image size
+    pub fn fraction(&self) -> Vector2<f32> {
+        let (x, y) = match self {
+            Anchor::TopLeft => (0.0, 0.0),
+            Anchor::Top => (0.5, 0.0),
+            Anchor::TopRight => (1.0, 0.0),
+            Anchor::Left => (0.0, 0.5),
+            Anchor::Center => (0.5, 0.5),
+            Anchor::Right => (1.0, 0.5),
+            Anchor::BottomLeft => (0.0, 1.0),
+            Anchor::Bottom => (0.5, 1.0),
+            Anchor::BottomRight => (1.0, 1.0),
+        };
+        Vector2::new(x, y)
+    }
+}
+
+/// How close to the divider a click grabs it, in screen pixels
+const GRAB_DISTANCE: f32 = 10.0;
+
+/// A vertical divider between two images that can be dragged across the window
+#[derive(Debug, Clone, Copy)]
+pub struct Split {
+    /// Relative to the window width
+    pub position: f32,
+    pub dragging: bool,
+}
+
+impl Default for Split {
+    fn default() -> Self {
+        Split {
+            position: 0.5,
+            dragging: false,
+        }
+    }
+}
+
+impl Split {
+    pub fn x(&self, window_width: f32) -> f32 {
+        self.position * window_width
+    }
+
+    /// Start dragging if the cursor is on the divider. Returns whether it was grabbed.
+    pub fn grab(&mut self, cursor_x: f32, window_width: f32) -> bool {
+        self.dragging = (cursor_x - self.x(window_width)).abs() < GRAB_DISTANCE;
+        self.dragging
+    }
+
+    pub fn drag(&mut self, cursor_x: f32, window_width: f32) {
+        if self.dragging && window_width > 0.0 {
+            self.position = (cursor_x / window_width).clamp(0.0, 1.0);
+        }
+    }
+
+    /// The column of an image drawn at `origin_x` that is under the divider
+    pub fn column(&self, window_width: f32, origin_x: f32, scale: f32, image_width: f32) -> f32 {
+        ((self.x(window_width) - origin_x) / scale).clamp(0.0, image_width)
+    }
+
+    pub fn draw(&self, draw: &mut Draw, window: Vector2<f32>) {
+        let x = self.x(window.x);
+        draw.line((x, 0.0), (x, window.y))
+            .width(2.0)
+            .color(Color::WHITE);
+    }
+}
+
+/// Draw some columns of `texture`, as if the whole texture was drawn at `origin`
+fn draw_columns(
+    draw: &mut Draw,
+    texture: &Texture,
+    origin: Vector2<f32>,
+    scale: f32,
+    columns: Range<f32>,
+) {
+    let width = columns.end - columns.start;
+    if width <= 0.0 {
+        return;
+    }
+    draw.image(texture)
+        .blend_mode(BlendMode::NORMAL)
+        .crop((columns.start, 0.0), (width, texture.height()))
+        .size(width, texture.height())
+        .translate(origin.x + columns.start * scale, origin.y)
+        .scale(scale, scale);
+}
+
+/// The first frame of an image file
+fn load_still(path: &Path) -> Result<RgbaImage> {
+    open_image(&path.to_path_buf())?
+        .frames
+        .into_iter()
+        .next()
+        .map(|f| f.buffer)
+        .ok_or(anyhow!("{} contains no image", path.display()))
+}
+
+/// Print the metrics of two image files. With a threshold, returns whether they are within it.
+pub fn print_metrics(
+    first: &Path,
+    second: &Path,
+    threshold: Option<(Metric, f64)>,
+) -> Result<bool> {
+    let metrics = diff::metrics(&load_still(first)?, &load_still(second)?)?;
+    print!("{metrics}");
+    Ok(match threshold {
+        Some((metric, value)) => {
+            let within = metrics.within(metric, value);
+            if !within {
+                println!(
+                    "{metric} of {} exceeds the threshold of {value}",
+                    metrics.value(metric)
+                );
+            }
+            within
+        }
+        None => true,
+    })
+}
+
+/// A second image shown next to or on top of the current one. Both share zoom and pan.
+#[derive(Debug)]
+pub struct Compare {
+    pub active: bool,
+    pub mode: CompareMode,
+    pub anchor: Anchor,
+    pub path: Option<PathBuf>,
+    pub image: Option<RgbaImage>,
+    pub texture: Option<Texture>,
+    /// Position in the second image matching the cursor
+    pub cursor_relative: Vector2<f32>,
+    /// Color of the second image under the cursor, if it is inside of it
+    pub sampled_color: Option<[f32; 4]>,
+    /// Divider of the wipe mode
+    pub split: Split,
+    /// Flicker mode shows the second image
+    pub show_second: bool,
+    /// Factor the difference is multiplied with to make small changes visible
+    pub amplification: f32,
+    /// Channel differences up to this are not counted as changed
+    pub threshold: u8,
+    /// Number of changed pixels, once the mask was computed
+    pub changed_pixels: Option<usize>,
+    /// Difference or mask texture, computed in the background when needed
+    overlay: Option<Texture>,
+    /// Whether `overlay` matches the current images and settings, or is being computed
+    overlay_valid: bool,
+    overlay_receiver: Option<Receiver<(RgbaImage, Option<usize>)>>,
+    /// Quality metrics of the two images, computed in the background when needed
+    pub metrics: Option<Result<Metrics, String>>,
+    metrics_receiver: Option<Receiver<Result<Metrics>>>,
+    receiver: Option<Receiver<Result<RgbaImage>>>,
+}
+
+impl Default for Compare {
+    fn default() -> Self {
+        Compare {
+            active: false,
+            mode: Default::default(),
+            anchor: Default::default(),
+            path: None,
+            image: None,
+            texture: None,
+            cursor_relative: Vector2::zeros(),
+            sampled_color: None,
+            split: Default::default(),
+            show_second: false,
+            amplification: 1.0,
+            threshold: 0,
+            changed_pixels: None,
+            overlay: None,
+            overlay_valid: false,
+            overlay_receiver: None,
+            metrics: None,
+            metrics_receiver: None,
+            receiver: None,
+        }
+    }
+}
+
+impl Compare {
+    /// Load `path` as the second image in the background
+    pub fn load(&mut self, path: &Path) {
+        let (sender, receiver) = mpsc::channel();
+        let location = path.to_path_buf();
+        std::thread::spawn(move || {
+            _ = sender.send(load_still(&location));
+        });
+        self.receiver = Some(receiver);
+        self.path = Some(path.to_path_buf());
+        self.active = true;
+    }
+
+    pub fn stop(&mut self) {
+        *self = Compare {
+            mode: self.mode,
+            anchor: self.anchor,
+            amplification: self.amplification,
+            threshold: self.threshold,
+            split: self.split,
+            ..Default::default()
+        };
+    }
+
+    /// Compute the difference or mask again, as one of the images or a setting changed.
+    /// The old one is shown until then.
+    pub fn invalidate_overlay(&mut self) {
+        self.overlay_valid = false;
+    }
+
+    /// Compute the metrics again, as one of the images changed
+    pub fn invalidate_metrics(&mut self) {
+        self.metrics = None;
+        self.metrics_receiver = None;
+    }
+
+    /// Start computing the metrics of `first` and the second image, unless they are known
+    pub fn request_metrics(&mut self, first: &RgbaImage) {
+        let second = match &self.image {
+            Some(second) if self.metrics.is_none() && self.metrics_receiver.is_none() => second,
+            _ => return,
+        };
+        let (sender, receiver) = mpsc::channel();
+        let (first, second) = (first.clone(), second.clone());
+        std::thread::spawn(move || {
+            _ = sender.send(diff::metrics(&first, &second));
+        });
+        self.metrics_receiver = Some(receiver);
+    }
+
+    /// Pick up computed metrics
+    pub fn receive_metrics(&mut self) {
+        let result = match self.metrics_receiver.as_ref().map(|r| r.try_recv()) {
+            Some(Ok(result)) => result.map_err(|e| e.to_string()),
+            Some(Err(mpsc::TryRecvError::Disconnected)) => Err("Computing metrics failed".into()),
+            Some(Err(mpsc::TryRecvError::Empty)) | None => return,
+        };
+        self.metrics = Some(result);
+        self.metrics_receiver = None;
+    }
+
+    /// Whether metrics or an overlay are being computed
+    pub fn is_computing(&self) -> bool {
+        self.metrics_receiver.is_some() || self.overlay_receiver.is_some()
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    /// Pick up a loaded image. Returns true once it arrived.
+    pub fn receive(&mut self, gfx: &mut Graphics, filter: TextureFilter) -> Result<bool> {
+        let result = match self.receiver.as_ref().map(|r| r.try_recv()) {
+            Some(Ok(result)) => result,
+            Some(Err(mpsc::TryRecvError::Empty)) | None => return Ok(false),
+            Some(Err(mpsc::TryRecvError::Disconnected)) => Err(anyhow!("Loading failed")),
+        };
+        self.receiver = None;
+        match result {
+            Ok(image) => {
+                debug!("Compare image arrived");
+                self.texture = image.to_texture_with_filter(gfx, filter);
+                self.image = Some(image);
+                self.invalidate_overlay();
+                self.invalidate_metrics();
+                Ok(true)
+            }
+            Err(e) => {
+                self.active = false;
+                Err(e)
+            }
+        }
+    }
+
+    fn size(&self) -> Option<Vector2<f32>> {
+        self.image
+            .as_ref()
+            .map(|i| Vector2::new(i.width() as f32, i.height() as f32))
+    }
+
+    /// What has to be added to a position in the first image to get the
+    /// matching position in the second one
+    pub fn shift(&self, first: Vector2<f32>) -> Vector2<f32> {
+        match self.size() {
+            Some(second) => (second - first).component_mul(&self.anchor.fraction()),
+            None => Vector2::zeros(),
+        }
+    }
+
+    /// Where the second image is drawn, in pixels of the first image
+    pub fn origin(&self, first: Vector2<f32>) -> Vector2<f32> {
+        let shift = self.shift(first);
+        match self.mode {
+            CompareMode::SideBySide => Vector2::new(first.x + GAP, -shift.y),
+            CompareMode::Stacked => Vector2::new(-shift.x, first.y + GAP),
+            _ => -shift,
+        }
+    }
+
+    /// Top left corner and size of the second image, in pixels of the first image
+    pub fn second_rect(&self, first: Vector2<f32>) -> Option<(Vector2<f32>, Vector2<f32>)> {
+        Some((self.origin(first), self.size()?))
+    }
+
+    /// Top left corner and size of the area covered by both images
+    pub fn bounds(&self, first: Vector2<f32>) -> (Vector2<f32>, Vector2<f32>) {
+        let second = match self.size() {
+            Some(second) => second,
+            None => return (Vector2::zeros(), first),
+        };
+        let origin = self.origin(first);
+        let min = Vector2::new(origin.x.min(0.0), origin.y.min(0.0));
+        let max = Vector2::new(
+            (origin.x + second.x).max(first.x),
+            (origin.y + second.y).max(first.y),
+        );
+        (min, max - min)
+    }
+
+    /// Positions in the first and second image under the cursor. `pos` is the cursor
+    /// in pixels of the first image, and may be outside of it. Whichever image is hovered
+    /// determines the position, the other one shows the matching pixel.
+    pub fn probe(&self, pos: Vector2<f32>, first: Vector2<f32>) -> (Vector2<f32>, Vector2<f32>) {
+        let shift = self.shift(first);
+        if self.mode.is_overlay() {
+            return (pos, pos + shift);
+        }
+        let in_second = pos - self.origin(first);
+        let second = self.size().unwrap_or(first);
+        let over_second = in_second.x >= 0.0
+            && in_second.y >= 0.0
+            && in_second.x < second.x
+            && in_second.y < second.y;
+        if over_second {
+            (in_second - shift, in_second)
+        } else {
+            (pos, pos + shift)
+        }
+    }
+
+    /// Upload the second image again with another texture filter
+    pub fn refresh_texture(&mut self, gfx: &mut Graphics, filter: TextureFilter) {
+        if let Some(image) = &self.image {
+            self.texture = image.to_texture_with_filter(gfx, filter);
+        }
+        self.invalidate_overlay();
+    }
+
+    /// Pick up a computed difference or mask, and start computing a new one from `first`
+    /// and the second image if it is outdated. Only one is computed at a time, so dragging
+    /// a slider does not pile up work.
+    pub fn update_overlay(&mut self, first: &RgbaImage, gfx: &mut Graphics, filter: TextureFilter) {
+        match self.overlay_receiver.as_ref().map(|r| r.try_recv()) {
+            Some(Ok((overlay, changed_pixels))) => {
+                debug!("Computed {} overlay", self.mode);
+                self.overlay = overlay.to_texture_with_filter(gfx, filter);
+                self.changed_pixels = changed_pixels;
+                self.overlay_receiver = None;
+            }
+            Some(Err(mpsc::TryRecvError::Disconnected)) => {
+                // compute it again instead of keeping the outdated one
+                error!("Computing the {} overlay failed", self.mode);
+                self.overlay_receiver = None;
+                self.overlay_valid = false;
+            }
+            Some(Err(mpsc::TryRecvError::Empty)) => return,
+            None => (),
+        }
+        let (mode, amplification, threshold) = (self.mode, self.amplification, self.threshold);
+        if self.overlay_valid
+            || !matches!(mode, CompareMode::Difference | CompareMode::ChangedPixels)
+        {
+            return;
+        }
+        let second = match &self.image {
+            Some(second) => second.clone(),
+            None => return,
+        };
+        let shift = self.shift(Vector2::new(first.width() as f32, first.height() as f32));
+        let shift = (shift.x.round() as i64, shift.y.round() as i64);
+        let (sender, receiver) = mpsc::channel();
+        let first = first.clone();
+        std::thread::spawn(move || {
+            _ = sender.send(match mode {
+                CompareMode::ChangedPixels => {
+                    let (mask, count) = diff::changed_pixels(&first, &second, shift, threshold);
+                    (mask, Some(count))
+                }
+                _ => (
+                    diff::difference(&first, &second, shift, amplification),
+                    None,
+                ),
+            });
+        });
+        self.overlay_receiver = Some(receiver);
+        self.overlay_valid = true;
+    }
+
+    /// Draw the second image, or what the mode shows instead, over the first one.
+    /// `first` is the size of the first image.
+    pub fn draw(
+        &self,
+        draw: &mut Draw,
+        offset: Vector2<f32>,
+        scale: f32,
+        first: Vector2<f32>,
+        window: Vector2<f32>,
+    ) {
+        let texture = match &self.texture {
+            Some(texture) if self.active => texture,
+            _ => return,
+        };
+        let origin = offset + self.origin(first) * scale;
+        match self.mode {
+            CompareMode::SideBySide | CompareMode::Stacked => {
+                draw.image(texture)
+                    .blend_mode(BlendMode::NORMAL)
+                    .translate(origin.x, origin.y)
+                    .scale(scale, scale);
+            }
+            CompareMode::Flicker => {
+                if self.show_second {
+                    draw.image(texture)
+                        .blend_mode(BlendMode::NORMAL)
+                        .translate(origin.x, origin.y)
+                        .scale(scale, scale);
+                }
+            }
+            CompareMode::Wipe => {
+                let column = self
+                    .split
+                    .column(window.x, origin.x, scale, texture.width());
+                draw_columns(draw, texture, origin, scale, column..texture.width());
+                self.split.draw(draw, window);
+            }
+            CompareMode::Difference | CompareMode::ChangedPixels => {
+                if let Some(overlay) = &self.overlay {
+                    draw.image(overlay)
+                        .blend_mode(BlendMode::NORMAL)
+                        .translate(offset.x, offset.y)
+                        .scale(scale, scale);
+                }
+            }
+        }
+    }
+}
+
+/// Ways to see the original image while editing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, Display)]
+pub enum BeforeAfterMode {
+    Off,
+    /// The original left of a movable split
+    Wipe,
+    #[strum(serialize = "Side by side")]
+    SideBySide,
+}
+
+impl Default for BeforeAfterMode {
+    fn default() -> Self {
+        BeforeAfterMode::Off
+    }
+}
+
+/// The original image shown with the edit result, at the same zoom and offset
+#[derive(Debug, Default)]
+pub struct BeforeAfter {
+    pub mode: BeforeAfterMode,
+    pub split: Split,
+    texture: Option<Texture>,
+}
+
+impl BeforeAfter {
+    /// Create the texture of the original again, as it changed
+    pub fn invalidate(&mut self) {
+        self.texture = None;
+    }
+
+    /// Draw `original` over the left part of the edited image, or to the left of it
+    pub fn draw(
+        &mut self,
+        draw: &mut Draw,
+        gfx: &mut Graphics,
+        original: &RgbaImage,
+        filter: TextureFilter,
+        offset: Vector2<f32>,
+        scale: f32,
+        window: Vector2<f32>,
+    ) {
+        if self.mode == BeforeAfterMode::Off {
+            return;
+        }
+        if self.texture.is_none() {
+            self.texture = original.to_texture_with_filter(gfx, filter);
+        }
+        let texture = match &self.texture {
+            Some(texture) => texture,
+            None => return,
+        };
+        match self.mode {
+            BeforeAfterMode::Off => (),
+            BeforeAfterMode::Wipe => {
+                let column = self
+                    .split
+                    .column(window.x, offset.x, scale, texture.width());
+                draw_columns(draw, texture, offset, scale, 0.0..column);
+                self.split.draw(draw, window);
+            }
+            BeforeAfterMode::SideBySide => {
+                let x = offset.x - (texture.width() + GAP) * scale;
+                draw.image(texture)
+                    .blend_mode(BlendMode::NORMAL)
+                    .translate(x, offset.y)
+                    .scale(scale, scale);
+            }
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use anyhow::{bail, Result};
+use image::{Rgba, RgbaImage};
+use rayon::prelude::*;
+use std::collections::VecDeque;
+use std::fmt;
+use strum::Display;
+use strum_macros::EnumString;
+
+/// Marks a pixel that differs in the changed pixels mask
+const CHANGED: Rgba<u8> = Rgba([255, 0, 75, 255]);
+
+/// The pixel of `b` matching (`x`, `y`) in the other image, or transparent black outside of `b`
+fn counterpart(b: &RgbaImage, shift: (i64, i64), x: u32, y: u32) -> Rgba<u8> {
+    let (bx, by) = (x as i64 + shift.0, y as i64 + shift.1);
+    if bx < 0 || by < 0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    b.get_pixel_checked(bx as u32, by as u32)
+        .copied()
+        .unwrap_or(Rgba([0, 0, 0, 0]))
+}
+
+/// Absolute difference of the color channels, multiplied by `amplification`.
+/// The result has the size of `a`. `shift` is added to positions in `a` to get the
+/// matching positions in `b`, pixels without one are compared to transparent black.
+pub fn difference(
+    a: &RgbaImage,
+    b: &RgbaImage,
+    shift: (i64, i64),
+    amplification: f32,
+) -> RgbaImage {
+    RgbaImage::from_fn(a.width(), a.height(), |x, y| {
+        let pa = a.get_pixel(x, y);
+        let pb = counterpart(b, shift, x, y);
+        let mut out = Rgba([0, 0, 0, 255]);
+        for c in 0..3 {
+            let d = (pa[c] as f32 - pb[c] as f32).abs() * amplification;
+            out[c] = d.min(255.0) as u8;
+        }
+        out
+    })
+}
+
+/// A mask of all pixels where any channel differs by more than `threshold`,
+/// and the number of these pixels. Unchanged pixels are transparent.
+pub fn changed_pixels(
+    a: &RgbaImage,
+    b: &RgbaImage,
+    shift: (i64, i64),
+    threshold: u8,
+) -> (RgbaImage, usize) {
+    let mut count = 0;
+    let mask = RgbaImage::from_fn(a.width(), a.height(), |x, y| {
+        let pa = a.get_pixel(x, y);
+        let pb = counterpart(b, shift, x, y);
+        let changed = (0..4).any(|c| pa[c].abs_diff(pb[c]) > threshold);
+        if changed {
+            count += 1;
+            CHANGED
+        } else {
+            Rgba([0, 0, 0, 0])
+        }
+    });
+    (mask, count)
+}
+
+/// A single number summarizing how much two images differ
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Metric {
+    Mse,
+    Psnr,
+    Ssim,
+    MaxError,
+}
+
+impl Metric {
+    /// Whether larger values mean more similar images
+    pub fn higher_is_better(&self) -> bool {
+        matches!(self, Metric::Psnr | Metric::Ssim)
+    }
+}
+
+/// Similarity of two images of the same size, per channel in RGBA order.
+/// The combined values cover the color channels, as alpha is often constant.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metrics {
+    pub mse: [f64; 4],
+    pub psnr: [f64; 4],
+    pub ssim: [f64; 4],
+    pub max_error: [u8; 4],
+    pub combined_mse: f64,
+    pub combined_psnr: f64,
+    pub combined_ssim: f64,
+    pub combined_max_error: u8,
+}
+
+impl Metrics {
+    pub fn value(&self, metric: Metric) -> f64 {
+        match metric {
+            Metric::Mse => self.combined_mse,
+            Metric::Psnr => self.combined_psnr,
+            Metric::Ssim => self.combined_ssim,
+            Metric::MaxError => self.combined_max_error as f64,
+        }
+    }
+
+    /// Whether the images are at least as similar as `threshold` requires
+    pub fn within(&self, metric: Metric, threshold: f64) -> bool {
+        if metric.higher_is_better() {
+            self.value(metric) >= threshold
+        } else {
+            self.value(metric) <= threshold
+        }
+    }
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<10}{:>10}{:>10}{:>10}{:>10}{:>10}",
+            "", "R", "G", "B", "A", "RGB"
+        )?;
+        let rows: [(&str, [f64; 4], f64, usize); 4] = [
+            ("MSE", self.mse, self.combined_mse, 3),
+            ("PSNR", self.psnr, self.combined_psnr, 2),
+            ("SSIM", self.ssim, self.combined_ssim, 5),
+            (
+                "Max error",
+                self.max_error.map(|e| e as f64),
+                self.combined_max_error as f64,
+                0,
+            ),
+        ];
+        for (name, channels, combined, precision) in rows {
+            write!(f, "{name:<10}")?;
+            for value in channels.iter().chain([combined].iter()) {
+                write!(f, "{value:>10.precision$}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Peak signal to noise ratio in dB, infinite for identical images
+pub fn psnr(mse: f64) -> f64 {
+    if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (255.0 * 255.0 / mse).log10()
+    }
+}
+
+/// Compare two images of the same size
+pub fn metrics(a: &RgbaImage, b: &RgbaImage) -> Result<Metrics> {
+    if a.dimensions() != b.dimensions() {
+        bail!(
+            "Sizes differ: {}x{} and {}x{}",
+            a.width(),
+            a.height(),
+            b.width(),
+            b.height()
+        );
+    }
+    let pixels = (a.width() as f64 * a.height() as f64).max(1.0);
+    let mut squared = [0.0; 4];
+    let mut max_error = [0u8; 4];
+    for (pa, pb) in a.pixels().zip(b.pixels()) {
+        for c in 0..4 {
+            let d = pa[c].abs_diff(pb[c]);
+            squared[c] += d as f64 * d as f64;
+            max_error[c] = max_error[c].max(d);
+        }
+    }
+    let mse = squared.map(|s| s / pixels);
+    let ssim = [0, 1, 2, 3].map(|c| channel_ssim(a, b, c));
+    let combined_mse = mse[..3].iter().sum::<f64>() / 3.0;
+    Ok(Metrics {
+        mse,
+        psnr: mse.map(psnr),
+        ssim,
+        max_error,
+        combined_mse,
+        combined_psnr: psnr(combined_mse),
+        combined_ssim: ssim[..3].iter().sum::<f64>() / 3.0,
+        combined_max_error: max_error[..3].iter().copied().max().unwrap_or_default(),
+    })
+}
+
+/// Standard deviation of the Gaussian SSIM window
+const SSIM_SIGMA: f64 = 1.5;
+/// The SSIM window is 11x11 pixels
+const SSIM_RADIUS: usize = 5;
+/// Rows of SSIM windows each thread computes at once
+const SSIM_ROWS_PER_TASK: usize = 64;
+
+/// Normalized weights of a Gaussian window reaching `radius` pixels from its middle
+fn gaussian_window(radius: usize) -> Vec<f64> {
+    let weights = (0..=2 * radius)
+        .map(|i| {
+            let d = i as f64 - radius as f64;
+            (-d * d / (2.0 * SSIM_SIGMA * SSIM_SIGMA)).exp()
+        })
+        .collect::<Vec<_>>();
+    let sum = weights.iter().sum::<f64>();
+    weights.iter().map(|w| w / sum).collect()
+}
+
+/// Mean structural similarity of one channel as defined by Wang et al. (2004), the way
+/// reference implementations compute it: an 11x11 Gaussian window with σ = 1.5 is placed
+/// at every position where it fits into the image. Images smaller than that use a
+/// window as large as fits.
+fn channel_ssim(a: &RgbaImage, b: &RgbaImage, channel: usize) -> f64 {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+    let (width, height) = (a.width() as usize, a.height() as usize);
+    if width == 0 || height == 0 {
+        return 1.0;
+    }
+    let radius_x = SSIM_RADIUS.min((width - 1) / 2);
+    let radius_y = SSIM_RADIUS.min((height - 1) / 2);
+    let (weights_x, weights_y) = (gaussian_window(radius_x), gaussian_window(radius_y));
+    let (columns, rows) = (width - 2 * radius_x, height - 2 * radius_y);
+
+    // means of a, b, a², b² and ab around each window position of a row, weighted horizontally
+    let blur_row = |y: usize| -> [Vec<f64>; 5] {
+        let values = |img: &RgbaImage| {
+            (0..width as u32)
+                .map(|x| img.get_pixel(x, y as u32)[channel] as f64)
+                .collect::<Vec<_>>()
+        };
+        let (row_a, row_b) = (values(a), values(b));
+        let mut stats = [(); 5].map(|_| vec![0.0; columns]);
+        for x in 0..columns {
+            for (i, w) in weights_x.iter().enumerate() {
+                let (va, vb) = (row_a[x + i], row_b[x + i]);
+                stats[0][x] += w * va;
+                stats[1][x] += w * vb;
+                stats[2][x] += w * va * va;
+                stats[3][x] += w * vb * vb;
+                stats[4][x] += w * va * vb;
+            }
+        }
+        stats
+    };
+
+    let tasks = (0..rows).step_by(SSIM_ROWS_PER_TASK).collect::<Vec<_>>();
+    let total = tasks
+        .par_iter()
+        .map(|&first_row| {
+            let last_row = (first_row + SSIM_ROWS_PER_TASK).min(rows);
+            // the rows the current window covers, weighted horizontally
+            let mut window = (first_row..first_row + 2 * radius_y)
+                .map(blur_row)
+                .collect::<VecDeque<_>>();
+            let mut total = 0.0;
+            for y in first_row..last_row {
+                window.push_back(blur_row(y + 2 * radius_y));
+                for x in 0..columns {
+                    let mut stats = [0.0; 5];
+                    for (row, w) in window.iter().zip(&weights_y) {
+                        for (stat, values) in stats.iter_mut().zip(row) {
+                            *stat += w * values[x];
+                        }
+                    }
+                    let [mean_a, mean_b, sq_a, sq_b, prod] = stats;
+                    let var_a = sq_a - mean_a * mean_a;
+                    let var_b = sq_b - mean_b * mean_b;
+                    let cov = prod - mean_a * mean_b;
+                    total += ((2.0 * mean_a * mean_b + C1) * (2.0 * cov + C2))
+                        / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+                }
+                window.pop_front();
+            }
+            total
+        })
+        .sum::<f64>();
+    total / (columns * rows) as f64
+}
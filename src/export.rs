@@ -0,0 +1,180 @@
+use anyhow::{anyhow, bail, Result};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, EncodableLayout};
+use libwebp_sys::*;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use crate::gif_stream::{is_gif, GifStream};
+use crate::sequence::{self, ImageSequence};
+use crate::utils::{open_image, Frame};
+
+/// File formats animations can be exported to
+pub const ANIMATION_FORMATS: [&str; 3] = ["gif", "apng", "webp"];
+
+/// Frames without a delay are played back at this rate, same as the player
+const DEFAULT_DELAY: u16 = 40;
+
+fn frame_delay(frame: &Frame) -> u16 {
+    if frame.delay > 0 {
+        frame.delay
+    } else {
+        DEFAULT_DELAY
+    }
+}
+
+/// All frames of what is being played: the files of `sequence` at `fps` if there is one,
+/// otherwise the frames of `path`. Gifs are streamed like in the player.
+pub fn animation_frames(
+    path: &Path,
+    sequence: Option<&ImageSequence>,
+    fps: f32,
+) -> Result<Vec<Frame>> {
+    if let Some(sequence) = sequence {
+        let delay = sequence::frame_delay(fps);
+        return sequence
+            .files
+            .iter()
+            .map(|(_, file)| sequence::decode_frame(file, delay))
+            .collect();
+    }
+    if is_gif(path) {
+        let mut stream = GifStream::new(path)?;
+        let mut frames = vec![];
+        while let Some(frame) = stream.decode_next() {
+            frames.push(frame);
+        }
+        return Ok(frames);
+    }
+    Ok(open_image(&path.to_path_buf())?.frames)
+}
+
+/// Write all frames as an infinitely looping animation. The format is chosen by
+/// `format`, one of `ANIMATION_FORMATS`.
+pub fn export_animation(frames: &[Frame], path: &Path, format: &str) -> Result<()> {
+    let first = frames.first().ok_or(anyhow!("No frames to export"))?;
+    if frames
+        .iter()
+        .any(|f| f.buffer.dimensions() != first.buffer.dimensions())
+    {
+        bail!("All frames must have the same size to be exported as animation");
+    }
+
+    match format {
+        "gif" => encode_gif(frames, path),
+        "apng" => encode_apng(frames, path),
+        "webp" => encode_webp(frames, path),
+        _ => Err(anyhow!("Animation format {format} is not supported")),
+    }
+}
+
+fn encode_gif(frames: &[Frame], path: &Path) -> Result<()> {
+    let mut encoder = GifEncoder::new_with_speed(BufWriter::new(File::create(path)?), 10);
+    encoder.set_repeat(Repeat::Infinite)?;
+    encoder.encode_frames(frames.iter().map(|f| {
+        image::Frame::from_parts(
+            f.buffer.clone(),
+            0,
+            0,
+            Delay::from_numer_denom_ms(frame_delay(f) as u32, 1),
+        )
+    }))?;
+    Ok(())
+}
+
+fn encode_apng(frames: &[Frame], path: &Path) -> Result<()> {
+    let (width, height) = frames[0].buffer.dimensions();
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    // zero plays means looping forever
+    encoder.set_animated(frames.len() as u32, 0)?;
+    let mut writer = encoder.write_header()?;
+    for frame in frames {
+        writer.set_frame_delay(frame_delay(frame), 1000)?;
+        writer.write_image_data(frame.buffer.as_bytes())?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+// Unsafe animated webp encoding using webp-sys
+fn encode_webp(frames: &[Frame], path: &Path) -> Result<()> {
+    let (width, height) = frames[0].buffer.dimensions();
+
+    unsafe {
+        let mut config: WebPConfig = std::mem::zeroed();
+        if WebPConfigInitInternal(
+            &mut config,
+            WebPPreset::WEBP_PRESET_DEFAULT,
+            90.,
+            WEBP_ENCODER_ABI_VERSION as i32,
+        ) == 0
+        {
+            bail!("Can't initialize webp config");
+        }
+
+        // The default options loop forever
+        let mut options: WebPAnimEncoderOptions = std::mem::zeroed();
+        if WebPAnimEncoderOptionsInitInternal(&mut options, WEBP_MUX_ABI_VERSION as i32) == 0 {
+            bail!("Can't initialize webp animation options");
+        }
+        let encoder = WebPAnimEncoderNewInternal(
+            width as i32,
+            height as i32,
+            &options,
+            WEBP_MUX_ABI_VERSION as i32,
+        );
+        if encoder.is_null() {
+            bail!("Can't create webp animation encoder");
+        }
+
+        let mut timestamp = 0;
+        for frame in frames {
+            let mut picture: WebPPicture = std::mem::zeroed();
+            WebPPictureInitInternal(&mut picture, WEBP_ENCODER_ABI_VERSION as i32);
+            picture.use_argb = 1;
+            picture.width = width as i32;
+            picture.height = height as i32;
+            let ok = WebPPictureImportRGBA(&mut picture, frame.buffer.as_ptr(), width as i32 * 4)
+                != 0
+                && WebPAnimEncoderAdd(encoder, &mut picture, timestamp, &config) != 0;
+            WebPPictureFree(&mut picture);
+            if !ok {
+                WebPAnimEncoderDelete(encoder);
+                bail!("Can't encode webp frame");
+            }
+            timestamp += frame_delay(frame) as i32;
+        }
+        // Flush, using the end timestamp for the duration of the last frame
+        WebPAnimEncoderAdd(encoder, std::ptr::null_mut(), timestamp, std::ptr::null());
+
+        let mut data = WebPData {
+            bytes: std::ptr::null(),
+            size: 0,
+        };
+        let assembled = WebPAnimEncoderAssemble(encoder, &mut data) != 0;
+        WebPAnimEncoderDelete(encoder);
+        if !assembled {
+            bail!("Can't assemble webp animation");
+        }
+        let result = std::fs::write(path, std::slice::from_raw_parts(data.bytes, data.size));
+        WebPFree(data.bytes as *mut _);
+        Ok(result?)
+    }
+}
+
+/// Write every frame as a numbered png into `dir`, using `stem` as the file name prefix
+pub fn export_sequence(frames: &[Frame], dir: &Path, stem: &str) -> Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir)?;
+    // pad numbers so files sort correctly
+    let digits = frames.len().to_string().len().max(4);
+    let mut written = vec![];
+    for (i, frame) in frames.iter().enumerate() {
+        let path = dir.join(format!("{stem}_{i:0digits$}.png"));
+        frame.buffer.save(&path)?;
+        written.push(path);
+    }
+    Ok(written)
+}
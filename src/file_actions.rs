@@ -0,0 +1,245 @@
+use anyhow::{anyhow, bail, Result};
+use log::debug;
+use std::fmt;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::xmp;
+
+/// Number of quick folders images can be moved or copied to
+pub const QUICK_FOLDERS: usize = 5;
+
+/// A file operation that was done, with everything needed to undo it.
+/// xmp sidecars go along with their images.
+#[derive(Debug, Clone)]
+pub enum FileAction {
+    Trash {
+        path: PathBuf,
+        sidecar: Option<PathBuf>,
+    },
+    Move {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Copy {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+    },
+}
+
+impl fmt::Display for FileAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileAction::Trash { path, .. } => write!(f, "Moved {} to the trash", file_name(path)),
+            FileAction::Move { from, to } => {
+                write!(f, "Moved {} to {}", file_name(from), parent(to))
+            }
+            FileAction::Copy { from, to } => {
+                write!(f, "Copied {} to {}", file_name(from), parent(to))
+            }
+            FileAction::Rename { from, to } => {
+                write!(f, "Renamed {} to {}", file_name(from), file_name(to))
+            }
+        }
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn parent(path: &Path) -> String {
+    path.parent()
+        .map(|p| p.display().to_string())
+        .unwrap_or_default()
+}
+
+/// Where `path` ends up in `dir`. Existing files are never overwritten.
+fn target_in(path: &Path, dir: &Path) -> Result<PathBuf> {
+    if !dir.is_dir() {
+        bail!("{} is not a folder", dir.display());
+    }
+    let target = dir.join(path.file_name().ok_or(anyhow!("Invalid file name"))?);
+    if target.exists() {
+        bail!("{} already exists", target.display());
+    }
+    Ok(target)
+}
+
+/// Move a file, copying it if it needs to go to another file system
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if to.exists() {
+        bail!("{} already exists", to.display());
+    }
+    match std::fs::rename(from, to) {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            debug!("{} is on another device, copying it", from.display());
+            std::fs::copy(from, to)?;
+            if let Err(e) = std::fs::remove_file(from) {
+                // don't leave a second copy behind
+                _ = std::fs::remove_file(to);
+                return Err(e.into());
+            }
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The sidecar of `image`, if it has one
+fn existing_sidecar(image: &Path) -> Option<PathBuf> {
+    Some(xmp::sidecar_path(image)).filter(|p| p.is_file())
+}
+
+/// The sidecar of `from`, if it has one, and what it is called once `from` became `to`
+fn sidecar_target(from: &Path, to: &Path) -> Result<Option<(PathBuf, PathBuf)>> {
+    let sidecar = match existing_sidecar(from) {
+        Some(sidecar) => sidecar,
+        None => return Ok(None),
+    };
+    // keep the naming scheme of the sidecar
+    let target = if sidecar == from.with_extension("xmp") {
+        to.with_extension("xmp")
+    } else {
+        let mut full = to.as_os_str().to_owned();
+        full.push(".xmp");
+        PathBuf::from(full)
+    };
+    if target.exists() {
+        bail!("{} already exists", target.display());
+    }
+    Ok(Some((sidecar, target)))
+}
+
+/// Move an image and its sidecar. The image is moved back if the sidecar can't follow.
+fn move_with_sidecar(from: &Path, to: &Path) -> Result<()> {
+    let sidecar = sidecar_target(from, to)?;
+    move_file(from, to)?;
+    if let Some((sidecar, target)) = sidecar {
+        if let Err(e) = move_file(&sidecar, &target) {
+            move_file(to, from).map_err(|undo| {
+                anyhow!("{e}. {} stays at {}: {undo}", file_name(from), parent(to))
+            })?;
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Move a file to the trash. Files are never deleted for good.
+pub fn trash(path: &Path) -> Result<FileAction> {
+    let path = path.canonicalize()?;
+    let sidecar = existing_sidecar(&path);
+    trash::delete(&path)?;
+    if let Some(sidecar) = &sidecar {
+        trash::delete(sidecar)?;
+    }
+    Ok(FileAction::Trash { path, sidecar })
+}
+
+pub fn move_to(path: &Path, dir: &Path) -> Result<FileAction> {
+    let to = target_in(path, dir)?;
+    move_with_sidecar(path, &to)?;
+    Ok(FileAction::Move {
+        from: path.to_path_buf(),
+        to,
+    })
+}
+
+pub fn copy_to(path: &Path, dir: &Path) -> Result<FileAction> {
+    let to = target_in(path, dir)?;
+    let sidecar = sidecar_target(path, &to)?;
+    std::fs::copy(path, &to)?;
+    if let Some((sidecar, target)) = sidecar {
+        if let Err(e) = std::fs::copy(sidecar, target) {
+            _ = std::fs::remove_file(&to);
+            return Err(e.into());
+        }
+    }
+    Ok(FileAction::Copy {
+        from: path.to_path_buf(),
+        to,
+    })
+}
+
+/// Give a file a new name in the same folder
+pub fn rename(path: &Path, name: &str) -> Result<FileAction> {
+    if name.is_empty() || name.contains(['/', '\\']) {
+        bail!("Invalid file name: {name}");
+    }
+    let to = path.with_file_name(name);
+    move_with_sidecar(path, &to)?;
+    Ok(FileAction::Rename {
+        from: path.to_path_buf(),
+        to,
+    })
+}
+
+/// Revert a file action. Returns the file that is back in its original place.
+pub fn undo(action: &FileAction) -> Result<PathBuf> {
+    match action {
+        FileAction::Trash { path, sidecar } => {
+            restore(path)?;
+            if let Some(sidecar) = sidecar {
+                restore(sidecar)?;
+            }
+            Ok(path.clone())
+        }
+        FileAction::Move { from, to } | FileAction::Rename { from, to } => {
+            move_with_sidecar(to, from)?;
+            Ok(from.clone())
+        }
+        FileAction::Copy { from, to } => {
+            // the copy goes to the trash as well, in case it was changed since
+            let sidecar = existing_sidecar(to);
+            trash::delete(to)?;
+            if let Some(sidecar) = sidecar {
+                trash::delete(sidecar)?;
+            }
+            Ok(from.clone())
+        }
+    }
+}
+
+/// Restore the most recently trashed file that came from `path`
+#[cfg(any(
+    target_os = "windows",
+    all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )
+))]
+fn restore(path: &Path) -> Result<()> {
+    let item = trash::os_limited::list()?
+        .into_iter()
+        .filter(|item| item.original_path() == path)
+        .max_by_key(|item| item.time_deleted)
+        .ok_or(anyhow!("{} is not in the trash", path.display()))?;
+    trash::os_limited::restore_all(vec![item])?;
+    Ok(())
+}
+
+#[cfg(not(any(
+    target_os = "windows",
+    all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )
+)))]
+fn restore(path: &Path) -> Result<()> {
+    bail!(
+        "Restoring from the trash is not supported here. {} is still in the trash.",
+        file_name(path)
+    )
+}
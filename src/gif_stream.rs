@@ -0,0 +1,177 @@
+use anyhow::Result;
+use image::RgbaImage;
+use log::{debug, error};
+use rgb::ComponentBytes;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::utils::{AnimationSource, Frame, FrameSource, FrameStore};
+
+/// How much memory decoded gif frames may use. Longer animations are decoded again on every loop.
+const MAX_CACHE_BYTES: usize = 512 << 20;
+
+pub fn is_gif(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase() == "gif")
+        .unwrap_or_default()
+}
+
+/// Decodes gif frames as they are needed instead of all at once
+pub struct GifStream {
+    path: PathBuf,
+    decoder: gif::Decoder<File>,
+    screen: gif_dispose::Screen,
+    /// Index of the frame the decoder produces next
+    position: usize,
+    /// Delays of all frames decoded so far
+    delays: Vec<u16>,
+    /// All frames have been decoded at least once
+    complete: bool,
+    plays: Option<u32>,
+    cache: FrameStore,
+}
+
+impl GifStream {
+    pub fn new(path: &Path) -> Result<Self> {
+        let (decoder, screen) = open_decoder(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            decoder,
+            screen,
+            position: 0,
+            delays: vec![],
+            complete: false,
+            plays: loop_count(path)?,
+            cache: FrameStore::new(MAX_CACHE_BYTES),
+        })
+    }
+
+    /// Decode the next frame. Returns `None` once the animation ends.
+    pub fn decode_next(&mut self) -> Option<Frame> {
+        // Stopping at errors is a workaround for partially corrupt gifs.
+        let frame = match self.decoder.read_next_frame() {
+            Ok(Some(frame)) => frame,
+            Ok(None) => return None,
+            Err(e) => {
+                error!("Stopped decoding gif: {e}");
+                return None;
+            }
+        };
+        if let Err(e) = self.screen.blit_frame(frame) {
+            error!("Can't read gif frame: {e}");
+            return None;
+        }
+        let delay = frame.delay * 10;
+        let buffer = RgbaImage::from_raw(
+            self.decoder.width() as u32,
+            self.decoder.height() as u32,
+            self.screen.pixels.buf().as_bytes().to_vec(),
+        )?;
+
+        if self.position == self.delays.len() {
+            self.delays.push(delay);
+        }
+        self.position += 1;
+        Some(Frame::new(buffer, delay, FrameSource::Animation))
+    }
+
+    /// Start decoding from the first frame again
+    fn rewind(&mut self) -> Result<()> {
+        debug!("Rewinding gif");
+        let (decoder, screen) = open_decoder(&self.path)?;
+        self.decoder = decoder;
+        self.screen = screen;
+        self.position = 0;
+        Ok(())
+    }
+}
+
+impl AnimationSource for GifStream {
+    fn frame(&mut self, index: usize) -> Option<Frame> {
+        if let Some(frame) = self.cache.get(index) {
+            return Some(frame);
+        }
+        if self.complete && index >= self.delays.len() {
+            return None;
+        }
+        if index < self.position {
+            if let Err(e) = self.rewind() {
+                error!("{e}");
+                return None;
+            }
+        }
+        // Frames depend on the previous ones, so decode up to the requested one
+        loop {
+            let current = self.position;
+            match self.decode_next() {
+                Some(frame) => {
+                    self.cache.insert(current, frame.clone());
+                    if current == index {
+                        return Some(frame);
+                    }
+                }
+                None => {
+                    self.complete = true;
+                    return None;
+                }
+            }
+        }
+    }
+
+    fn frame_count(&self) -> usize {
+        self.delays.len()
+    }
+
+    fn delay(&self, index: usize) -> u16 {
+        self.delays.get(index).copied().unwrap_or_default()
+    }
+
+    fn has_frame(&mut self, index: usize) -> bool {
+        index < self.delays.len() || (!self.complete && self.frame(index).is_some())
+    }
+
+    fn complete(&self) -> bool {
+        self.complete
+    }
+
+    fn plays(&self) -> Option<u32> {
+        self.plays
+    }
+}
+
+fn open_decoder(path: &Path) -> Result<(gif::Decoder<File>, gif_dispose::Screen)> {
+    let mut gif_opts = gif::DecodeOptions::new();
+    gif_opts.set_color_output(gif::ColorOutput::Indexed);
+    let decoder = gif_opts.read_info(File::open(path)?)?;
+    let screen = gif_dispose::Screen::new_decoder(&decoder);
+    Ok((decoder, screen))
+}
+
+/// How often a gif should be played according to its NETSCAPE2.0 extension.
+/// `None` means forever, which is also assumed if the extension is missing.
+pub fn loop_count(path: &Path) -> Result<Option<u32>> {
+    // The extension comes before the first image, right after the global palette
+    let mut header = vec![];
+    File::open(path)?.take(4096).read_to_end(&mut header)?;
+    Ok(parse_loop_count(&header))
+}
+
+fn parse_loop_count(data: &[u8]) -> Option<u32> {
+    for id in [&b"NETSCAPE2.0"[..], &b"ANIMEXTS1.0"[..]] {
+        if let Some(pos) = data.windows(id.len()).position(|w| w == id) {
+            // sub block: length 3, id 1, then the loop count as little endian u16
+            let block = data.get(pos + id.len()..pos + id.len() + 4)?;
+            if block[0] == 3 && block[1] == 1 {
+                let loops = u16::from_le_bytes([block[2], block[3]]);
+                // zero loops forever, otherwise this counts the repetitions after the first play
+                return if loops == 0 {
+                    None
+                } else {
+                    Some(loops as u32 + 1)
+                };
+            }
+        }
+    }
+    None
+}
@@ -50,6 +50,23 @@ impl Default for EditState {
     }
 }
 
+impl EditState {
+    /// Run all operators and paint strokes on a copy of `img`
+    pub fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        let mut result = img.clone();
+        for operation in &self.image_op_stack {
+            operation.process_image(&mut result);
+        }
+        if !self.pixel_op_stack.is_empty() {
+            process_pixels(&mut result, &self.pixel_op_stack);
+        }
+        for stroke in &self.paint_strokes {
+            stroke.render(&mut result, &self.brushes);
+        }
+        result
+    }
+}
+
 fn default_brushes() -> Vec<RgbaImage> {
     vec![
         image::load_from_memory(include_bytes!("../res/brushes/brush1.png"))
@@ -0,0 +1,461 @@
+//! Loading of KTX (1.1) and KTX2 texture containers.
+//! Uncompressed 8 bit formats and BC1-BC5 block compression are supported.
+use anyhow::{anyhow, bail, Result};
+use image::RgbaImage;
+use log::debug;
+use std::convert::TryInto;
+use std::io::Read;
+use std::path::Path;
+
+const KTX1_MAGIC: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x31, 0x31, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+const KTX2_MAGIC: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+/// Textures have at most one mip level per bit of their size
+const MAX_LEVELS: u32 = 32;
+
+// OpenGL enums used by KTX 1.1 headers
+const GL_UNSIGNED_BYTE: u32 = 0x1401;
+const GL_RED: u32 = 0x1903;
+const GL_RGB: u32 = 0x1907;
+const GL_RGBA: u32 = 0x1908;
+const GL_LUMINANCE: u32 = 0x1909;
+const GL_BGRA: u32 = 0x80E1;
+const GL_RG: u32 = 0x8227;
+const GL_COMPRESSED_RGB_S3TC_DXT1: u32 = 0x83F0;
+const GL_COMPRESSED_RGBA_S3TC_DXT1: u32 = 0x83F1;
+const GL_COMPRESSED_RGBA_S3TC_DXT3: u32 = 0x83F2;
+const GL_COMPRESSED_RGBA_S3TC_DXT5: u32 = 0x83F3;
+const GL_COMPRESSED_SRGB_S3TC_DXT1: u32 = 0x8C4C;
+const GL_COMPRESSED_SRGB_ALPHA_S3TC_DXT1: u32 = 0x8C4D;
+const GL_COMPRESSED_SRGB_ALPHA_S3TC_DXT3: u32 = 0x8C4E;
+const GL_COMPRESSED_SRGB_ALPHA_S3TC_DXT5: u32 = 0x8C4F;
+const GL_COMPRESSED_RED_RGTC1: u32 = 0x8DBB;
+const GL_COMPRESSED_RG_RGTC2: u32 = 0x8DBD;
+
+/// The pixel layout of a mip level
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TexelFormat {
+    R8,
+    Rg8,
+    Rgb8,
+    Bgr8,
+    Rgba8,
+    Bgra8,
+    Bc1,
+    Bc2,
+    Bc3,
+    Bc4,
+    Bc5,
+}
+
+impl TexelFormat {
+    fn bytes_per_pixel(&self) -> usize {
+        match self {
+            Self::R8 => 1,
+            Self::Rg8 => 2,
+            Self::Rgb8 | Self::Bgr8 => 3,
+            Self::Rgba8 | Self::Bgra8 => 4,
+            _ => 0,
+        }
+    }
+
+    fn bytes_per_block(&self) -> usize {
+        match self {
+            Self::Bc1 | Self::Bc4 => 8,
+            Self::Bc2 | Self::Bc3 | Self::Bc5 => 16,
+            _ => 0,
+        }
+    }
+
+    fn is_block_compressed(&self) -> bool {
+        self.bytes_per_block() != 0
+    }
+}
+
+/// A single mip level, still encoded
+struct Level<'a> {
+    width: u32,
+    height: u32,
+    data: &'a [u8],
+}
+
+pub fn is_ktx(path: &Path) -> bool {
+    match path
+        .extension()
+        .unwrap_or_default()
+        .to_str()
+        .unwrap_or_default()
+        .to_lowercase()
+        .as_str()
+    {
+        "ktx" | "ktx2" => true,
+        _ => false,
+    }
+}
+
+/// Number of mip levels in a KTX file, as stated by its header.
+/// Returns 1 for anything that is not a KTX file.
+pub fn level_count(path: &Path) -> u32 {
+    if !is_ktx(path) {
+        return 1;
+    }
+    read_level_count(path).unwrap_or(1).clamp(1, MAX_LEVELS)
+}
+
+fn read_level_count(path: &Path) -> Result<u32> {
+    let mut header = [0u8; 64];
+    std::fs::File::open(path)?.read_exact(&mut header)?;
+    if header[0..12] == KTX2_MAGIC {
+        Ok(u32::from_le_bytes(header[40..44].try_into()?))
+    } else if header[0..12] == KTX1_MAGIC {
+        let count: [u8; 4] = header[56..60].try_into()?;
+        // The endianness field reads as 0x04030201 if the file matches our byte order
+        if u32::from_le_bytes(header[12..16].try_into()?) == 0x04030201 {
+            Ok(u32::from_le_bytes(count))
+        } else {
+            Ok(u32::from_be_bytes(count))
+        }
+    } else {
+        bail!("Not a KTX texture")
+    }
+}
+
+/// Decode a mip level of a KTX or KTX2 texture. Level 0 is the full resolution image.
+/// For arrays and cube maps, the first layer / face is returned.
+pub fn open_ktx(path: &Path, level: u32) -> Result<RgbaImage> {
+    let data = std::fs::read(path)?;
+    let (format, levels, row_alignment) = parse_levels(&data)?;
+    let level = levels.get(level as usize).ok_or(anyhow!(
+        "Mip level {level} does not exist, the texture has {} levels",
+        levels.len()
+    ))?;
+    debug!(
+        "Decoding {:?} KTX level with {}x{}",
+        format, level.width, level.height
+    );
+    decode_level(format, level, row_alignment)
+}
+
+/// Split a KTX/KTX2 buffer into its mip levels.
+/// Also returns the row alignment of uncompressed data (KTX 1.1 pads rows to 4 bytes)
+fn parse_levels(data: &[u8]) -> Result<(TexelFormat, Vec<Level<'_>>, usize)> {
+    if data.len() < 12 {
+        bail!("File is too small to be a KTX texture");
+    }
+    if data[0..12] == KTX2_MAGIC {
+        let (format, levels) = parse_ktx2(data)?;
+        Ok((format, levels, 1))
+    } else if data[0..12] == KTX1_MAGIC {
+        let (format, levels) = parse_ktx1(data)?;
+        Ok((format, levels, 4))
+    } else {
+        bail!("Not a KTX texture")
+    }
+}
+
+fn parse_ktx2(data: &[u8]) -> Result<(TexelFormat, Vec<Level<'_>>)> {
+    use ktx2::Format;
+
+    let reader = ktx2::Reader::new(data).map_err(|e| anyhow!("{:?}", e))?;
+    let header = reader.header();
+
+    if let Some(scheme) = header.supercompression_scheme {
+        bail!(
+            "Supercompressed KTX2 textures ({:?}) are not supported",
+            scheme
+        );
+    }
+
+    let format = match header.format {
+        Some(Format::R8_UNORM) | Some(Format::R8_SRGB) => TexelFormat::R8,
+        Some(Format::R8G8_UNORM) | Some(Format::R8G8_SRGB) => TexelFormat::Rg8,
+        Some(Format::R8G8B8_UNORM) | Some(Format::R8G8B8_SRGB) => TexelFormat::Rgb8,
+        Some(Format::B8G8R8_UNORM) | Some(Format::B8G8R8_SRGB) => TexelFormat::Bgr8,
+        Some(Format::R8G8B8A8_UNORM) | Some(Format::R8G8B8A8_SRGB) => TexelFormat::Rgba8,
+        Some(Format::B8G8R8A8_UNORM) | Some(Format::B8G8R8A8_SRGB) => TexelFormat::Bgra8,
+        Some(Format::BC1_RGB_UNORM_BLOCK)
+        | Some(Format::BC1_RGB_SRGB_BLOCK)
+        | Some(Format::BC1_RGBA_UNORM_BLOCK)
+        | Some(Format::BC1_RGBA_SRGB_BLOCK) => TexelFormat::Bc1,
+        Some(Format::BC2_UNORM_BLOCK) | Some(Format::BC2_SRGB_BLOCK) => TexelFormat::Bc2,
+        Some(Format::BC3_UNORM_BLOCK) | Some(Format::BC3_SRGB_BLOCK) => TexelFormat::Bc3,
+        Some(Format::BC4_UNORM_BLOCK) => TexelFormat::Bc4,
+        Some(Format::BC5_UNORM_BLOCK) => TexelFormat::Bc5,
+        Some(f) => bail!("KTX2 format {:?} is not supported", f),
+        None => bail!("KTX2 textures without a format (Basis Universal) are not supported"),
+    };
+
+    // The reader has validated the level index, but its level slices borrow from the reader.
+    // Read the index directly so the levels can borrow from `data` instead.
+    // Each entry is made of byte offset, byte length and uncompressed byte length (u64 each).
+    if header.level_count > MAX_LEVELS {
+        bail!("KTX2 texture has too many mip levels");
+    }
+    let mut levels = vec![];
+    for i in 0..header.level_count.max(1) as usize {
+        let entry = 80 + i * 24;
+        let offset = u64::from_le_bytes(data[entry..entry + 8].try_into()?) as usize;
+        let length = u64::from_le_bytes(data[entry + 8..entry + 16].try_into()?) as usize;
+        let end = offset
+            .checked_add(length)
+            .ok_or(anyhow!("KTX2 mip level {i} has an invalid size"))?;
+        levels.push(Level {
+            width: (header.pixel_width >> i).max(1),
+            height: (header.pixel_height >> i).max(1),
+            data: data
+                .get(offset..end)
+                .ok_or(anyhow!("KTX2 mip level {i} is truncated"))?,
+        });
+    }
+
+    Ok((format, levels))
+}
+
+fn parse_ktx1(data: &[u8]) -> Result<(TexelFormat, Vec<Level<'_>>)> {
+    if data.len() < 64 {
+        bail!("KTX header is truncated");
+    }
+    // The endianness field reads as 0x04030201 if the file matches our byte order
+    let swap = u32::from_le_bytes([data[12], data[13], data[14], data[15]]) != 0x04030201;
+    let read_u32 = |offset: usize| -> Result<u32> {
+        let bytes: [u8; 4] = data
+            .get(offset..offset + 4)
+            .ok_or(anyhow!("KTX data is truncated"))?
+            .try_into()?;
+        Ok(if swap {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+
+    let gl_type = read_u32(16)?;
+    let gl_format = read_u32(24)?;
+    let gl_internal_format = read_u32(28)?;
+    let width = read_u32(36)?;
+    let height = read_u32(40)?.max(1);
+    let array_elements = read_u32(48)?;
+    let faces = read_u32(52)?.max(1);
+    let level_count = read_u32(56)?.max(1);
+    if level_count > MAX_LEVELS {
+        bail!("KTX texture has too many mip levels");
+    }
+    let kv_bytes = read_u32(60)?;
+
+    let format = match (gl_type, gl_format, gl_internal_format) {
+        (0, _, GL_COMPRESSED_RGB_S3TC_DXT1)
+        | (0, _, GL_COMPRESSED_RGBA_S3TC_DXT1)
+        | (0, _, GL_COMPRESSED_SRGB_S3TC_DXT1)
+        | (0, _, GL_COMPRESSED_SRGB_ALPHA_S3TC_DXT1) => TexelFormat::Bc1,
+        (0, _, GL_COMPRESSED_RGBA_S3TC_DXT3) | (0, _, GL_COMPRESSED_SRGB_ALPHA_S3TC_DXT3) => {
+            TexelFormat::Bc2
+        }
+        (0, _, GL_COMPRESSED_RGBA_S3TC_DXT5) | (0, _, GL_COMPRESSED_SRGB_ALPHA_S3TC_DXT5) => {
+            TexelFormat::Bc3
+        }
+        (0, _, GL_COMPRESSED_RED_RGTC1) => TexelFormat::Bc4,
+        (0, _, GL_COMPRESSED_RG_RGTC2) => TexelFormat::Bc5,
+        (GL_UNSIGNED_BYTE, GL_RED, _) | (GL_UNSIGNED_BYTE, GL_LUMINANCE, _) => TexelFormat::R8,
+        (GL_UNSIGNED_BYTE, GL_RG, _) => TexelFormat::Rg8,
+        (GL_UNSIGNED_BYTE, GL_RGB, _) => TexelFormat::Rgb8,
+        (GL_UNSIGNED_BYTE, GL_RGBA, _) => TexelFormat::Rgba8,
+        (GL_UNSIGNED_BYTE, GL_BGRA, _) => TexelFormat::Bgra8,
+        _ => bail!(
+            "KTX format is not supported (type {:#x}, format {:#x}, internal format {:#x})",
+            gl_type,
+            gl_format,
+            gl_internal_format
+        ),
+    };
+
+    // Non-array cube maps store each face with its own padding, everything else is one block per level
+    let is_cubemap = faces == 6 && array_elements == 0;
+
+    let mut levels = vec![];
+    let mut offset = 64 + kv_bytes as usize;
+    for i in 0..level_count {
+        let image_size = read_u32(offset)? as usize;
+        offset += 4;
+        let end = offset
+            .checked_add(image_size)
+            .ok_or(anyhow!("KTX mip level {i} has an invalid size"))?;
+        let level_data = data
+            .get(offset..end)
+            .ok_or(anyhow!("KTX mip level {i} is truncated"))?;
+        levels.push(Level {
+            width: (width >> i).max(1),
+            height: (height >> i).max(1),
+            data: level_data,
+        });
+        let padded_size = (image_size + 3) / 4 * 4;
+        offset += if is_cubemap {
+            padded_size * 6
+        } else {
+            padded_size
+        };
+    }
+
+    Ok((format, levels))
+}
+
+fn decode_level(format: TexelFormat, level: &Level, row_alignment: usize) -> Result<RgbaImage> {
+    let (width, height) = (level.width as usize, level.height as usize);
+    let mut img = RgbaImage::new(level.width, level.height);
+
+    if format.is_block_compressed() {
+        let blocks_x = (width + 3) / 4;
+        let blocks_y = (height + 3) / 4;
+        let block_size = format.bytes_per_block();
+        if level.data.len() < blocks_x * blocks_y * block_size {
+            bail!("Not enough data for a {width}x{height} {format:?} texture");
+        }
+
+        for (i, block) in level
+            .data
+            .chunks_exact(block_size)
+            .take(blocks_x * blocks_y)
+            .enumerate()
+        {
+            let texels = decode_block(format, block);
+            let (bx, by) = ((i % blocks_x) * 4, (i / blocks_x) * 4);
+            for (t, texel) in texels.iter().enumerate() {
+                let (x, y) = (bx + t % 4, by + t / 4);
+                // blocks at the border may be partially outside of the image
+                if x < width && y < height {
+                    img.put_pixel(x as u32, y as u32, image::Rgba(*texel));
+                }
+            }
+        }
+    } else {
+        let bpp = format.bytes_per_pixel();
+        let stride = (width * bpp + row_alignment - 1) / row_alignment * row_alignment;
+        if level.data.len() < stride * (height - 1) + width * bpp {
+            bail!("Not enough data for a {width}x{height} {format:?} texture");
+        }
+        for (x, y, p) in img.enumerate_pixels_mut() {
+            let offset = y as usize * stride + x as usize * bpp;
+            let s = &level.data[offset..offset + bpp];
+            p.0 = match format {
+                TexelFormat::R8 => [s[0], s[0], s[0], 255],
+                TexelFormat::Rg8 => [s[0], s[1], 0, 255],
+                TexelFormat::Rgb8 => [s[0], s[1], s[2], 255],
+                TexelFormat::Bgr8 => [s[2], s[1], s[0], 255],
+                TexelFormat::Rgba8 => [s[0], s[1], s[2], s[3]],
+                TexelFormat::Bgra8 => [s[2], s[1], s[0], s[3]],
+                _ => unreachable!(),
+            };
+        }
+    }
+    Ok(img)
+}
+
+/// Decode a 4x4 block into 16 RGBA texels, row by row
+fn decode_block(format: TexelFormat, block: &[u8]) -> [[u8; 4]; 16] {
+    match format {
+        TexelFormat::Bc1 => decode_color_block(block, false),
+        TexelFormat::Bc2 => {
+            let mut texels = decode_color_block(&block[8..16], true);
+            for (i, texel) in texels.iter_mut().enumerate() {
+                let nibble = (block[i / 2] >> ((i % 2) * 4)) & 0x0F;
+                texel[3] = nibble * 17;
+            }
+            texels
+        }
+        TexelFormat::Bc3 => {
+            let mut texels = decode_color_block(&block[8..16], true);
+            let alpha = decode_alpha_block(&block[0..8]);
+            for (texel, a) in texels.iter_mut().zip(alpha) {
+                texel[3] = a;
+            }
+            texels
+        }
+        TexelFormat::Bc4 => decode_alpha_block(block).map(|r| [r, r, r, 255]),
+        TexelFormat::Bc5 => {
+            let red = decode_alpha_block(&block[0..8]);
+            let green = decode_alpha_block(&block[8..16]);
+            let mut texels = [[0, 0, 0, 255]; 16];
+            for (i, texel) in texels.iter_mut().enumerate() {
+                texel[0] = red[i];
+                texel[1] = green[i];
+            }
+            texels
+        }
+        _ => [[0, 0, 0, 0]; 16],
+    }
+}
+
+fn unpack_565(c: u16) -> [u8; 4] {
+    let r = ((c >> 11) & 0x1F) as u8;
+    let g = ((c >> 5) & 0x3F) as u8;
+    let b = (c & 0x1F) as u8;
+    [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+        255,
+    ]
+}
+
+/// Decode a BC1 color block. BC2 and BC3 always use the four color mode.
+fn decode_color_block(block: &[u8], force_four_colors: bool) -> [[u8; 4]; 16] {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (p0, p1) = (unpack_565(c0), unpack_565(c1));
+
+    let mix = |w0: u32, w1: u32| -> [u8; 4] {
+        let total = w0 + w1;
+        [
+            ((p0[0] as u32 * w0 + p1[0] as u32 * w1) / total) as u8,
+            ((p0[1] as u32 * w0 + p1[1] as u32 * w1) / total) as u8,
+            ((p0[2] as u32 * w0 + p1[2] as u32 * w1) / total) as u8,
+            255,
+        ]
+    };
+
+    let palette = if c0 > c1 || force_four_colors {
+        [p0, p1, mix(2, 1), mix(1, 2)]
+    } else {
+        [p0, p1, mix(1, 1), [0, 0, 0, 0]]
+    };
+
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    let mut texels = [[0; 4]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        *texel = palette[((indices >> (i * 2)) & 0b11) as usize];
+    }
+    texels
+}
+
+/// Decode a BC3 alpha / BC4 block into 16 single channel values
+fn decode_alpha_block(block: &[u8]) -> [u8; 16] {
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+    let mut palette = [0u8; 8];
+    palette[0] = a0 as u8;
+    palette[1] = a1 as u8;
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i + 1] = (((7 - i as u32) * a0 + i as u32 * a1) / 7) as u8;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = (((5 - i as u32) * a0 + i as u32 * a1) / 5) as u8;
+        }
+        palette[6] = 0;
+        palette[7] = 255;
+    }
+
+    let mut bits = 0u64;
+    for (i, b) in block[2..8].iter().enumerate() {
+        bits |= (*b as u64) << (8 * i);
+    }
+
+    let mut values = [0u8; 16];
+    for (i, v) in values.iter_mut().enumerate() {
+        *v = palette[((bits >> (i * 3)) & 0b111) as usize];
+    }
+    values
+}
@@ -2,6 +2,7 @@
 
 use arboard::Clipboard;
 use clap::Arg;
+use clap::ArgMatches;
 use clap::Command;
 use log::debug;
 use log::error;
@@ -13,7 +14,6 @@ use notan::egui::{self, *};
 use notan::prelude::*;
 use shortcuts::key_pressed;
 use shortcuts::lookup;
-use std::ffi::OsStr;
 use std::path::PathBuf;
 use std::sync::mpsc;
 use strum::IntoEnumIterator;
@@ -34,10 +34,32 @@ mod ui;
 mod update;
 use ui::*;
 
+use crate::background::BackgroundMode;
+use crate::compare::{BeforeAfterMode, CompareMode, Split};
 use crate::image_editing::EditState;
 
+mod background;
+mod compare;
+mod diff;
+mod export;
+mod file_actions;
+mod gif_stream;
 mod image_editing;
+mod ktx;
+mod measure;
+mod minimap;
+mod navigation;
+mod orientation;
 pub mod paint;
+mod pixel_grid;
+mod sequence;
+mod session;
+mod slideshow;
+mod svg;
+mod thumbnails;
+mod watch;
+mod xmp;
+mod zoom;
 
 #[notan_main]
 fn main() -> Result<(), String> {
@@ -53,6 +75,14 @@ fn main() -> Result<(), String> {
         let _ = env_logger::try_init();
     }
 
+    let matches = parse_args();
+    if let Some(dir) = matches.value_of("thumbnails") {
+        return thumbnails::pregenerate(&PathBuf::from(dir)).map_err(|e| e.to_string());
+    }
+    if matches.is_present("metrics") {
+        run_metrics(&matches);
+    }
+
     let mut window_config = WindowConfig::new()
         .title(&format!("Oculante | {}", env!("CARGO_PKG_VERSION")))
         .size(1026, 600) // window's size
@@ -101,12 +131,45 @@ fn main() -> Result<(), String> {
         .build()
 }
 
-fn init(_gfx: &mut Graphics, plugins: &mut Plugins) -> OculanteState {
-    info!("Now matching arguments {:?}", std::env::args());
+/// Print the metrics of the compared images for --metrics and exit
+fn run_metrics(matches: &ArgMatches) -> ! {
+    let threshold = match matches.value_of("threshold").map(parse_threshold) {
+        Some(Ok(threshold)) => Some(threshold),
+        Some(Err(e)) => {
+            eprintln!("{e}");
+            std::process::exit(2)
+        }
+        None => None,
+    };
+    let first = PathBuf::from(matches.value_of("INPUT").unwrap_or_default());
+    let second = PathBuf::from(matches.value_of("compare").unwrap_or_default());
+    match compare::print_metrics(&first, &second, threshold) {
+        Ok(true) => std::process::exit(0),
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2)
+        }
+    }
+}
+
+/// Parse a threshold like `psnr=40`
+fn parse_threshold(arg: &str) -> anyhow::Result<(diff::Metric, f64)> {
+    let (metric, value) = arg
+        .split_once('=')
+        .ok_or(anyhow::anyhow!("Threshold must look like psnr=40"))?;
+    let metric = metric.trim().parse::<diff::Metric>().map_err(|_| {
+        anyhow::anyhow!("Unknown metric {metric}, use mse, psnr, ssim or max-error")
+    })?;
+    Ok((metric, value.trim().parse()?))
+}
+
+/// Parse the command line arguments
+fn parse_args() -> ArgMatches {
     // Filter out strange mac args
     let args: Vec<String> = std::env::args().filter(|a| !a.contains("psn_")).collect();
 
-    let matches = Command::new("Oculante")
+    Command::new("Oculante")
         .arg(
             Arg::new("INPUT")
                 .help("Display this image")
@@ -126,7 +189,47 @@ fn init(_gfx: &mut Graphics, plugins: &mut Plugins) -> OculanteState {
                 .short('c')
                 .help("Chainload on Mac"),
         )
-        .get_matches_from(args);
+        .arg(
+            Arg::new("watch")
+                .long("watch")
+                .value_name("DIR")
+                .help("Watch DIR and show the newest image as soon as it is written")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("compare")
+                .long("compare")
+                .value_name("FILE")
+                .help("Show FILE next to the image to compare them")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("metrics")
+                .long("metrics")
+                .requires_all(&["INPUT", "compare"])
+                .help("Print MSE, PSNR, SSIM (11x11 Gaussian window, σ = 1.5) and max error of INPUT and the --compare image and exit"),
+        )
+        .arg(
+            Arg::new("threshold")
+                .long("threshold")
+                .value_name("METRIC=VALUE")
+                .requires("metrics")
+                .help("Exit with code 1 if the images differ more, e.g. psnr=40 or max-error=2. Errors exit with code 2.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("thumbnails")
+                .long("thumbnails")
+                .value_name("DIR")
+                .help("Generate cached thumbnails for all images in DIR and exit")
+                .takes_value(true),
+        )
+        .get_matches_from(args)
+}
+
+fn init(app: &mut App, _gfx: &mut Graphics, plugins: &mut Plugins) -> OculanteState {
+    info!("Now matching arguments {:?}", std::env::args());
+    let matches = parse_args();
 
     debug!("Completed argument parsing.");
 
@@ -159,6 +262,18 @@ fn init(_gfx: &mut Graphics, plugins: &mut Plugins) -> OculanteState {
         }
     }
 
+    if let Some(other) = matches.value_of("compare") {
+        state.compare.load(&PathBuf::from(other));
+    }
+
+    if let Some(dir) = matches.value_of("watch") {
+        let dir = PathBuf::from(dir);
+        if let Some(newest) = state.watch_folder.start(&dir) {
+            state.player.load(&newest, state.message_channel.0.clone());
+            state.current_path = Some(newest);
+        }
+    }
+
     if let Some(port) = matches.value_of("l") {
         match port.parse::<i32>() {
             Ok(p) => {
@@ -171,6 +286,14 @@ fn init(_gfx: &mut Graphics, plugins: &mut Plugins) -> OculanteState {
         }
     }
 
+    // nothing to show was given, so continue where we left off
+    if state.current_path.is_none() && state.persistent_settings.restore_session {
+        if let Some(session) = state.persistent_settings.session.clone() {
+            session.restore(&mut state);
+            app.window().set_always_on_top(state.always_on_top);
+        }
+    }
+
     // Set up egui style
     plugins.egui(|ctx| {
         let mut fonts = FontDefinitions::default();
@@ -206,6 +329,7 @@ fn init(_gfx: &mut Graphics, plugins: &mut Plugins) -> OculanteState {
 }
 
 fn event(app: &mut App, state: &mut OculanteState, evt: Event) {
+    state.last_input = std::time::Instant::now();
     match evt {
         Event::KeyUp { .. } => {
             // Fullscreen needs to be on key up on mac (bug)
@@ -232,16 +356,178 @@ fn event(app: &mut App, state: &mut OculanteState, evt: Event) {
             if key_pressed(app, state, ResetView) {
                 state.reset_image = true
             }
+            for (index, level) in zoom::ZOOM_LEVELS.iter().enumerate() {
+                if key_pressed(app, state, shortcuts::ZOOM[index].clone()) {
+                    zoom::zoom_centered(state, *level);
+                }
+            }
+            if key_pressed(app, state, RotateClockwise) {
+                rotate_view(state, true);
+            }
+            if key_pressed(app, state, RotateCounterClockwise) {
+                rotate_view(state, false);
+            }
+            if key_pressed(app, state, MirrorHorizontal) {
+                mirror_view(state, true);
+            }
+            if key_pressed(app, state, MirrorVertical) {
+                mirror_view(state, false);
+            }
+            if key_pressed(app, state, Minimap) {
+                state.persistent_settings.minimap = !state.persistent_settings.minimap;
+                _ = state.persistent_settings.save();
+            }
+            if key_pressed(app, state, Measure) {
+                toggle_measure(state);
+            }
+            if key_pressed(app, state, FitWidth) {
+                zoom::fit(state, zoom::FitMode::Width);
+            }
+            if key_pressed(app, state, FitHeight) {
+                zoom::fit(state, zoom::FitMode::Height);
+            }
+            if key_pressed(app, state, FillWindow) {
+                zoom::fit(state, zoom::FitMode::Fill);
+            }
 
             if key_pressed(app, state, Quit) {
+                session::save(state);
                 std::process::exit(0)
             }
 
-            if key_pressed(app, state, NextImage) {
-                next_image(state)
+            if key_pressed(app, state, ThumbnailGrid) {
+                toggle_thumbnail_grid(state);
             }
-            if key_pressed(app, state, PreviousImage) {
-                prev_image(state)
+
+            if state.thumbnail_grid.enabled {
+                let count = grid_files(state).len();
+                if key_pressed(app, state, NextImage) {
+                    state.thumbnail_grid.move_selection(1, count);
+                }
+                if key_pressed(app, state, PreviousImage) {
+                    state.thumbnail_grid.move_selection(-1, count);
+                }
+                if key_pressed(app, state, SelectionDown) {
+                    state.thumbnail_grid.move_rows(1, count);
+                }
+                if key_pressed(app, state, SelectionUp) {
+                    state.thumbnail_grid.move_rows(-1, count);
+                }
+                if key_pressed(app, state, OpenSelection) {
+                    if let Some(path) = grid_files(state).get(state.thumbnail_grid.selected) {
+                        open_from_grid(state, path.clone());
+                    }
+                }
+            } else {
+                if key_pressed(app, state, NextImage) {
+                    next_image(state)
+                }
+                if key_pressed(app, state, PreviousImage) {
+                    prev_image(state)
+                }
+            }
+
+            if key_pressed(app, state, Trash) {
+                run_file_action(state, file_actions::trash);
+            }
+            if key_pressed(app, state, Rename) {
+                state.rename_text = state
+                    .current_path
+                    .as_ref()
+                    .and_then(|p| p.file_name())
+                    .map(|n| n.to_string_lossy().to_string());
+            }
+            if key_pressed(app, state, UndoFileAction) {
+                undo_file_action(state);
+            }
+            for (rating, event) in shortcuts::RATE.iter().enumerate() {
+                if key_pressed(app, state, event.clone()) {
+                    rate(state, rating as u8);
+                }
+            }
+            for (label, event) in xmp::ColorLabel::iter().zip(shortcuts::LABEL.iter()) {
+                if key_pressed(app, state, event.clone()) {
+                    toggle_label(state, label);
+                }
+            }
+            if key_pressed(app, state, EditTags) {
+                state.tag_text = Some(String::new());
+            }
+            if key_pressed(app, state, Bookmark) {
+                toggle_bookmark(state);
+            }
+            if key_pressed(app, state, Compare) {
+                toggle_compare(state);
+            }
+            if key_pressed(app, state, Background) {
+                state.persistent_settings.background.toggle();
+                _ = state.persistent_settings.save();
+            }
+            if key_pressed(app, state, NearestFilter) {
+                state.persistent_settings.pixel_grid.nearest =
+                    !state.persistent_settings.pixel_grid.nearest;
+                state.refresh_texture = true;
+                _ = state.persistent_settings.save();
+            }
+            if key_pressed(app, state, PixelGrid) {
+                state.persistent_settings.pixel_grid.grid =
+                    !state.persistent_settings.pixel_grid.grid;
+                _ = state.persistent_settings.save();
+            }
+            if key_pressed(app, state, Flicker) && state.compare.active {
+                state.compare.mode = CompareMode::Flicker;
+                state.compare.show_second = !state.compare.show_second;
+            }
+            for index in 0..file_actions::QUICK_FOLDERS {
+                let move_pressed =
+                    key_pressed(app, state, shortcuts::MOVE_TO_FOLDER[index].clone());
+                let copy_pressed =
+                    key_pressed(app, state, shortcuts::COPY_TO_FOLDER[index].clone());
+                if !move_pressed && !copy_pressed {
+                    continue;
+                }
+                match state
+                    .persistent_settings
+                    .quick_folders
+                    .get(index)
+                    .filter(|dir| !dir.as_os_str().is_empty())
+                    .cloned()
+                {
+                    Some(dir) if move_pressed => {
+                        run_file_action(state, |p| file_actions::move_to(p, &dir))
+                    }
+                    Some(dir) => run_file_action(state, |p| file_actions::copy_to(p, &dir)),
+                    None => {
+                        state.message =
+                            Some(format!("Quick folder {} is not set up yet", index + 1))
+                    }
+                }
+            }
+
+            if key_pressed(app, state, Sequence) {
+                toggle_sequence(state);
+            }
+            if key_pressed(app, state, Slideshow) {
+                toggle_slideshow(app, state);
+            }
+
+            let pause_pressed = key_pressed(app, state, PauseAnimation);
+            if state.slideshow.active && pause_pressed {
+                state.slideshow.paused = !state.slideshow.paused;
+            } else if let Some(status) = state.player.status() {
+                if pause_pressed {
+                    state.player.command(if status.paused {
+                        PlayerCommand::Resume
+                    } else {
+                        PlayerCommand::Pause
+                    });
+                }
+                if key_pressed(app, state, NextFrame) {
+                    state.player.command(PlayerCommand::Step(1))
+                }
+                if key_pressed(app, state, PreviousFrame) {
+                    state.player.command(PlayerCommand::Step(-1))
+                }
             }
 
             if key_pressed(app, state, AlwaysOnTop) {
@@ -267,7 +553,7 @@ fn event(app: &mut App, state: &mut OculanteState, evt: Event) {
                 let delta = zoomratio(3.5, state.scale);
                 let new_scale = state.scale + delta;
                 // limit scale
-                if new_scale > 0.05 && new_scale < 40. {
+                if new_scale > zoom::MIN_SCALE && new_scale < zoom::MAX_SCALE {
                     // We want to zoom towards the center
                     let center: Vector2<f32> = nalgebra::Vector2::new(
                         app.window().width() as f32 / 2.,
@@ -282,7 +568,7 @@ fn event(app: &mut App, state: &mut OculanteState, evt: Event) {
                 let delta = zoomratio(-3.5, state.scale);
                 let new_scale = state.scale + delta;
                 // limit scale
-                if new_scale > 0.05 && new_scale < 40. {
+                if new_scale > zoom::MIN_SCALE && new_scale < zoom::MAX_SCALE {
                     // We want to zoom towards the center
                     let center: Vector2<f32> = nalgebra::Vector2::new(
                         app.window().width() as f32 / 2.,
@@ -302,15 +588,21 @@ fn event(app: &mut App, state: &mut OculanteState, evt: Event) {
                 let delta = zoomratio(delta_y, state.scale);
                 let new_scale = state.scale + delta;
                 // limit scale
-                if new_scale > 0.05 && new_scale < 40. {
+                if new_scale > zoom::MIN_SCALE && new_scale < zoom::MAX_SCALE {
                     state.offset -= scale_pt(state.offset, state.cursor, state.scale, delta);
                     state.scale += delta;
                 }
             }
         }
 
+        Event::Exit => session::save(state),
         Event::Drop(file) => {
             if let Some(p) = file.path {
+                // in compare mode, dropped images are compared with the current one
+                if state.compare.active && state.current_image.is_some() {
+                    state.compare.load(&p);
+                    return;
+                }
                 state.is_loaded = false;
                 state.current_image = None;
                 state.player.load(&p, state.message_channel.0.clone());
@@ -324,6 +616,20 @@ fn event(app: &mut App, state: &mut OculanteState, evt: Event) {
                     if !state.mouse_grab {
                         state.drag_enabled = true;
                     }
+                    // grab the wipe split instead of panning
+                    let (cursor_x, width) = (state.cursor.x, state.window_size.x);
+                    if !state.mouse_grab {
+                        if let Some(split) = active_split(state) {
+                            if split.grab(cursor_x, width) {
+                                state.drag_enabled = false;
+                            }
+                        }
+                    }
+                    // measure instead of panning
+                    if !state.mouse_grab && state.measure.is_active() {
+                        state.measure.begin(state.cursor_relative);
+                        state.drag_enabled = false;
+                    }
                 }
                 MouseButton::Middle => {
                     state.drag_enabled = true;
@@ -332,7 +638,13 @@ fn event(app: &mut App, state: &mut OculanteState, evt: Event) {
             }
         }
         Event::MouseUp { button, .. } => match button {
-            MouseButton::Left | MouseButton::Middle => state.drag_enabled = false,
+            MouseButton::Left | MouseButton::Middle => {
+                state.drag_enabled = false;
+                if let Some(split) = active_split(state) {
+                    split.dragging = false;
+                }
+                state.measure.dragging = false;
+            }
             _ => {}
         },
         _ => {
@@ -346,6 +658,11 @@ fn update(app: &mut App, state: &mut OculanteState) {
 
     state.mouse_delta = Vector2::new(mouse_pos.0, mouse_pos.1) - state.cursor;
     state.cursor = mouse_pos.size_vec();
+    let (cursor_x, width) = (state.cursor.x, state.window_size.x);
+    if let Some(split) = active_split(state) {
+        split.drag(cursor_x, width);
+    }
+
     if state.drag_enabled {
         if !state.mouse_grab || app.mouse.is_down(MouseButton::Middle) {
             state.offset += state.mouse_delta;
@@ -355,16 +672,34 @@ fn update(app: &mut App, state: &mut OculanteState) {
     // Since we can't access the window in the event loop, we store it in the state
     state.window_size = app.window().size().size_vec();
 
-    if state.info_enabled || state.edit_state.painting {
-        state.cursor_relative = pos_from_coord(
-            state.offset,
-            state.cursor,
-            Vector2::new(
-                state.image_dimension.0 as f32,
-                state.image_dimension.1 as f32,
-            ),
-            state.scale,
+    if state.info_enabled || state.edit_state.painting || state.measure.is_active() {
+        let dimension = Vector2::new(
+            state.image_dimension.0 as f32,
+            state.image_dimension.1 as f32,
         );
+        if state.compare.image.is_some() {
+            // the cursor may be over either image
+            let pos = (state.cursor - state.offset) / state.scale;
+            let (first, second) = state.compare.probe(pos, dimension);
+            state.cursor_relative =
+                pos_from_coord(Vector2::zeros(), first, dimension, 1.0, &Default::default());
+            state.compare.cursor_relative = second;
+        } else {
+            state.cursor_relative = pos_from_coord(
+                state.offset,
+                state.cursor,
+                dimension,
+                state.scale,
+                &orientation::current(state),
+            );
+        }
+    }
+
+    let cursor_relative = state.cursor_relative;
+    state.measure.drag(cursor_relative);
+
+    if state.compare.is_loading() || state.compare.is_computing() {
+        app.window().request_frame();
     }
 
     // redraw constantly until the image is fully loaded or it is reset on canvas
@@ -385,14 +720,9 @@ fn update(app: &mut App, state: &mut OculanteState) {
     }
 
     if state.reset_image {
-        let window_size = app.window().size().size_vec();
-        if let Some(current_image) = &state.current_image {
-            let img_size = current_image.size_vec();
-            let scale_factor = (window_size.x / img_size.x)
-                .min(window_size.y / img_size.y)
-                .min(1.0);
-            state.scale = scale_factor;
-            state.offset = window_size / 2.0 - (img_size * state.scale) / 2.0;
+        if state.current_image.is_some() {
+            let mode = state.persistent_settings.fit_mode;
+            zoom::fit(state, mode);
 
             state.edit_state = Default::default();
 
@@ -412,13 +742,88 @@ fn update(app: &mut App, state: &mut OculanteState) {
         }
     }
 
-    // reload constantly if gif so we keep receiving
-    if let Some(p) = &state.current_path {
-        if p.extension() == Some(OsStr::new("gif")) {
+    // render svgs again at the resolution they are displayed at
+    if let Some(renderer) = &mut state.svg_renderer {
+        if !state.edit_enabled && state.current_path.as_ref() == Some(&renderer.path) {
+            let raster_zoom = state.image_dimension.0 as f32 / renderer.base_size.0.max(1) as f32;
+            renderer.request(state.scale * raster_zoom);
+        }
+        if let Some(error) = renderer.failure() {
+            state.message = Some(error);
+        }
+        if renderer.awaiting_frame {
             app.window().request_frame();
         }
     }
 
+    if state.watch_folder.active {
+        if let Some(newest) = state.watch_folder.poll() {
+            debug!("New image in watched folder: {}", newest.display());
+            state.folder_listing.invalidate();
+            show_watched(state, newest);
+        }
+    }
+
+    // the watch folder mode takes care of changed files itself
+    if state.persistent_settings.auto_reload
+        && state.current_path.is_some()
+        && !state.network_mode
+        && !state.watch_folder.active
+    {
+        watch_current_file(state);
+    } else if state.folder_watcher.dir().is_some() {
+        state.folder_watcher.stop();
+    }
+
+    // nothing wakes up the window when watched files change, so keep looking for them
+    if state.watch_folder.active || state.folder_watcher.dir().is_some() {
+        // animations need every frame
+        if state.player.status().is_none() && !state.slideshow.active {
+            std::thread::sleep(watch::idle_wait(state.last_input));
+        }
+        app.window().request_frame();
+    }
+
+    // keep drawing while thumbnails arrive
+    if state.thumbnail_grid.enabled && state.thumbnail_grid.thumbnails.is_loading() {
+        app.window().request_frame();
+    }
+
+    // reload constantly if animated so we keep receiving
+    if state.player.status().is_some() {
+        app.window().request_frame();
+    }
+
+    if state.slideshow.active {
+        // keep drawing so the timer and fades progress
+        app.window().request_frame();
+        let interval = state.persistent_settings.slideshow.interval;
+        if state
+            .slideshow
+            .is_due(interval, state.player.status().as_ref())
+        {
+            let next = match &state.current_path {
+                Some(p) => {
+                    slideshow::next_slide(p, &mut state.folder_listing, &state.persistent_settings)
+                }
+                None => None,
+            };
+            match next {
+                Some(next) if Some(&next) != state.current_path.as_ref() => {
+                    state.slideshow.restart_timer();
+                    state.player.load(&next, state.message_channel.0.clone());
+                    state.current_path = Some(next);
+                }
+                // only one image, keep showing it
+                Some(_) => state.slideshow.restart_timer(),
+                None => {
+                    toggle_slideshow(app, state);
+                    state.message = Some("Slideshow finished".into());
+                }
+            }
+        }
+    }
+
     // check extended info has been sent
     if let Ok(info) = state.extended_info_channel.1.try_recv() {
         debug!("Finished calculating extended image info for {}", info.name);
@@ -435,22 +840,52 @@ fn update(app: &mut App, state: &mut OculanteState) {
 
 fn drawe(app: &mut App, gfx: &mut Graphics, plugins: &mut Plugins, state: &mut OculanteState) {
     let mut draw = gfx.create_draw();
-
-    // check if a new texture has been sent
-    if let Ok(frame) = state.texture_channel.1.try_recv() {
+    let filter = state.persistent_settings.pixel_grid.mag_filter();
+
+    // check if a new texture has been sent. Renders of an SVG that is not shown anymore are dropped.
+    let frame = state
+        .texture_channel
+        .1
+        .try_recv()
+        .ok()
+        .filter(|frame| match &frame.source {
+            FrameSource::Rerender(path) => state.current_path.as_ref() == Some(path),
+            _ => true,
+        });
+    if let Some(frame) = frame {
         let img = frame.buffer;
         debug!("Received image buffer:");
+        let previous_dimension = state.image_dimension;
         state.image_dimension = img.dimensions();
+        state.compare.invalidate_overlay();
+        state.before_after.invalidate();
+        // metrics take a while, so they are not updated for every frame of an animation
+        if frame.source != FrameSource::Animation {
+            state.compare.invalidate_metrics();
+        }
+
+        // keep the previous slide around to fade it out
+        if state.slideshow.active
+            && state.persistent_settings.slideshow.fade
+            && (frame.source == FrameSource::Still || frame.source == FrameSource::Reset)
+        {
+            state.slideshow.fade = state.current_texture.take().map(|texture| slideshow::Fade {
+                texture,
+                offset: state.offset,
+                scale: state.scale,
+                start: std::time::Instant::now(),
+            });
+        }
         // state.current_texture = img.to_texture(gfx);
 
         if let Some(tex) = &mut state.current_texture {
             if tex.width() as u32 == img.width() && img.height() as u32 == img.height() {
                 img.update_texture(gfx, tex);
             } else {
-                state.current_texture = img.to_texture(gfx);
+                state.current_texture = img.to_texture_with_filter(gfx, filter);
             }
         } else {
-            state.current_texture = img.to_texture(gfx);
+            state.current_texture = img.to_texture_with_filter(gfx, filter);
         }
 
         //center the image
@@ -460,16 +895,73 @@ fn drawe(app: &mut App, gfx: &mut Graphics, plugins: &mut Plugins, state: &mut O
 
         match frame.source {
             FrameSource::Still => {
-                state.offset = Default::default();
-                state.scale = Default::default();
-                state.reset_image = true;
+                state.slideshow.restart_timer();
+                // reloads of a changed file keep the view
+                if state.keep_view_for.take() != state.current_path {
+                    state.offset = Default::default();
+                    state.scale = Default::default();
+                    state.reset_image = true;
+                    state.orientation = state
+                        .current_path
+                        .as_ref()
+                        .map(|p| state.persistent_settings.orientation(p))
+                        .unwrap_or_default();
+                }
                 state.image_info = None;
+                if let Some(p) = state.current_path.as_ref().filter(|p| p.is_file()) {
+                    state.persistent_settings.add_recent(p);
+                }
+                state.xmp = state
+                    .current_path
+                    .as_ref()
+                    .map(|p| xmp::read(p))
+                    .unwrap_or_default();
+                state.measure.clear();
+                state.measure.dpi = state
+                    .current_path
+                    .as_ref()
+                    .and_then(|p| measure::read_dpi(p));
+                state.mip_level = 0;
+                state.mip_count = state
+                    .current_path
+                    .as_ref()
+                    .map(|p| ktx::level_count(p))
+                    .unwrap_or(1);
+                state.sequence = None;
+                state.svg_renderer = match &state.current_path {
+                    Some(p) if svg::is_svg(p) => Some(svg::SvgRenderer::new(
+                        p,
+                        img.dimensions(),
+                        state.texture_channel.0.clone(),
+                    )),
+                    _ => None,
+                };
+            }
+            FrameSource::Rerender(_) => {
+                // Keep the size on screen, as the new frame has a different resolution
+                if previous_dimension.0 != 0 {
+                    state.scale *= previous_dimension.0 as f32 / img.width() as f32;
+                }
+                if let Some(renderer) = &mut state.svg_renderer {
+                    renderer.awaiting_frame = false;
+                }
             }
             FrameSource::EditResult => {
                 // debug!("EditResult");
                 // state.edit_state.is_processing = false;
             }
-            FrameSource::Reset => state.reset_image = true,
+            FrameSource::Reset => {
+                state.slideshow.restart_timer();
+                if state.keep_view_for.take() != state.current_path {
+                    state.reset_image = true;
+                }
+                // Reset frames are also used to show other mip levels, so keep the selected level
+                state.mip_count = state
+                    .current_path
+                    .as_ref()
+                    .map(|p| ktx::level_count(p))
+                    .unwrap_or(1);
+            }
             _ => (),
         }
 
@@ -477,13 +969,15 @@ fn drawe(app: &mut App, gfx: &mut Graphics, plugins: &mut Plugins, state: &mut O
 
         match &state.current_channel {
             // Unpremultiply the image
-            Channel::RGB => state.current_texture = unpremult(&img).to_texture(gfx),
+            Channel::RGB => {
+                state.current_texture = unpremult(&img).to_texture_with_filter(gfx, filter)
+            }
             // Do nuttin'
             Channel::RGBA => (),
             // Display the channel
             _ => {
-                state.current_texture =
-                    solo_channel(&img, *&state.current_channel as usize).to_texture(gfx)
+                state.current_texture = solo_channel(&img, *&state.current_channel as usize)
+                    .to_texture_with_filter(gfx, filter)
             }
         }
         state.current_image = Some(img);
@@ -496,20 +990,106 @@ fn drawe(app: &mut App, gfx: &mut Graphics, plugins: &mut Plugins, state: &mut O
         }
     }
 
+    let had_compare_image = state.compare.image.is_some();
+    match state.compare.receive(gfx, filter) {
+        // fit both images when comparing starts, and keep the view when the second one changes.
+        // Edits of the first image are kept either way.
+        Ok(true) if !had_compare_image => {
+            let mode = state.persistent_settings.fit_mode;
+            zoom::fit(state, mode);
+        }
+        Ok(_) => (),
+        Err(e) => {
+            error!("Can't load compare image: {e}");
+            state.message = Some(format!("Can't compare: {e}"));
+        }
+    }
+
+    let orientation = orientation::current(state);
+
+    if state.persistent_settings.background.mode == BackgroundMode::Checkerboard {
+        if let Some(texture) = &state.current_texture {
+            let tiles = state.tiling.max(1) as f32;
+            let first = Vector2::new(texture.width(), texture.height());
+            let mut rects = vec![(Vector2::zeros(), orientation.view_size(first * tiles))];
+            if state.compare.active {
+                rects.extend(state.compare.second_rect(first));
+            }
+            for (origin, size) in rects {
+                state.checkerboard.draw(
+                    &mut draw,
+                    gfx,
+                    &state.persistent_settings.background,
+                    state.offset + origin * state.scale,
+                    size * state.scale,
+                );
+            }
+        }
+    }
+
+    // fade in the new slide over the previous one
+    let fade_alpha = state
+        .slideshow
+        .fade
+        .as_ref()
+        .map(|f| f.alpha())
+        .unwrap_or_default();
+    if let Some(fade) = &state.slideshow.fade {
+        draw.image(&fade.texture)
+            .blend_mode(BlendMode::NORMAL)
+            .translate(fade.offset.x, fade.offset.y)
+            .scale(fade.scale, fade.scale)
+            .alpha(fade_alpha);
+    }
+    if fade_alpha <= 0.0 {
+        state.slideshow.fade = None;
+    }
+
     if let Some(texture) = &state.current_texture {
         if state.tiling < 2 {
+            let size = Vector2::new(texture.width(), texture.height());
             draw.image(texture)
                 .blend_mode(BlendMode::NORMAL)
-                .translate(state.offset.x as f32, state.offset.y as f32)
-                .scale(state.scale, state.scale);
+                .transform(orientation.matrix(size, state.offset, state.scale))
+                .alpha(1.0 - fade_alpha);
+            if state.edit_enabled {
+                if let Some(img) = &state.current_image {
+                    state.before_after.draw(
+                        &mut draw,
+                        gfx,
+                        img,
+                        filter,
+                        state.offset,
+                        state.scale,
+                        state.window_size,
+                    );
+                }
+            }
+            if state.compare.active {
+                if let Some(img) = &state.current_image {
+                    state.compare.update_overlay(img, gfx, filter);
+                }
+                state.compare.draw(
+                    &mut draw,
+                    state.offset,
+                    state.scale,
+                    Vector2::new(
+                        state.image_dimension.0 as f32,
+                        state.image_dimension.1 as f32,
+                    ),
+                    state.window_size,
+                );
+            }
+            let size = Vector2::new(texture.width(), texture.height());
+            let (offset, scale) = (state.offset, state.scale);
+            state
+                .measure
+                .draw(&mut draw, |p| offset + orientation.to_view(p, size) * scale);
         } else {
+            let size = Vector2::new(texture.width(), texture.height()) * state.tiling as f32;
             draw.pattern(texture)
-                .translate(state.offset.x as f32, state.offset.y as f32)
-                .scale(state.scale, state.scale)
-                .size(
-                    texture.width() * state.tiling as f32,
-                    texture.height() * state.tiling as f32,
-                );
+                .size(size.x, size.y)
+                .transform(orientation.matrix(size, state.offset, state.scale));
         }
 
         // Draw a brush preview when paint mode is on
@@ -543,6 +1123,9 @@ fn drawe(app: &mut App, gfx: &mut Graphics, plugins: &mut Plugins, state: &mut O
     }
 
     let egui_output = plugins.egui(|ctx| {
+        // painted first, so the panels cover it
+        pixel_grid_ui(ctx, state);
+
         egui::TopBottomPanel::top("menu")
             .min_height(30.)
             .default_height(30.)
@@ -601,17 +1184,35 @@ fn drawe(app: &mut App, gfx: &mut Graphics, plugins: &mut Plugins, state: &mut O
                             });
                     });
 
+                    if state.refresh_texture {
+                        state.refresh_texture = false;
+                        // while editing, the texture shows the result instead of the image
+                        if state.edit_enabled && state.edit_state.result_pixel_op.width() > 0 {
+                            state.current_texture = state
+                                .edit_state
+                                .result_pixel_op
+                                .to_texture_with_filter(gfx, filter);
+                        } else {
+                            changed_channels = true;
+                        }
+                        state.compare.refresh_texture(gfx, filter);
+                        state.before_after.invalidate();
+                    }
+
                     if changed_channels {
                         if let Some(img) = &state.current_image {
                             match &state.current_channel {
                                 Channel::RGB => {
-                                    state.current_texture = unpremult(img).to_texture(gfx)
+                                    state.current_texture =
+                                        unpremult(img).to_texture_with_filter(gfx, filter)
+                                }
+                                Channel::RGBA => {
+                                    state.current_texture = img.to_texture_with_filter(gfx, filter)
                                 }
-                                Channel::RGBA => state.current_texture = img.to_texture(gfx),
                                 _ => {
                                     state.current_texture =
                                         solo_channel(img, *&state.current_channel as usize)
-                                            .to_texture(gfx)
+                                            .to_texture_with_filter(gfx, filter)
                                 }
                             }
                         }
@@ -639,6 +1240,43 @@ fn drawe(app: &mut App, gfx: &mut Graphics, plugins: &mut Plugins, state: &mut O
                             {
                                 next_image(state)
                             }
+                            if tooltip(
+                                unframed_button_colored("▦", state.thumbnail_grid.enabled, ui),
+                                "Thumbnails",
+                                &lookup(&state.persistent_settings.shortcuts, &ThumbnailGrid),
+                                ui,
+                            )
+                            .clicked()
+                            {
+                                toggle_thumbnail_grid(state);
+                            }
+                            if unframed_button_colored("👁", state.watch_folder.active, ui)
+                                .on_hover_text(
+                                    "Watch this folder and show new images as they arrive",
+                                )
+                                .clicked()
+                            {
+                                toggle_watch_folder(state);
+                            }
+                            if tooltip(
+                                unframed_button_colored("📽", state.slideshow.active, ui),
+                                "Slideshow",
+                                &lookup(&state.persistent_settings.shortcuts, &Slideshow),
+                                ui,
+                            )
+                            .clicked()
+                            {
+                                toggle_slideshow(app, state);
+                            }
+                            if state.slideshow.active {
+                                let icon = if state.slideshow.paused { "▶" } else { "⏸" };
+                                if unframed_button(icon, ui)
+                                    .on_hover_text("Pause or resume the slideshow")
+                                    .clicked()
+                                {
+                                    state.slideshow.paused = !state.slideshow.paused;
+                                }
+                            }
                         }
 
                         if tooltip(
@@ -662,10 +1300,87 @@ fn drawe(app: &mut App, gfx: &mut Graphics, plugins: &mut Plugins, state: &mut O
                             &lookup(&state.persistent_settings.shortcuts, &EditMode),
                             ui,
                         );
+
+                        zoom_ui(state, ui);
                     }
                     // TODO for windows/mac
                     // let mut window_pos = app.window().position();
 
+                    if state.current_path.as_ref().map(|p| p.is_file()) == Some(true) {
+                        xmp_ui(state, ui);
+                        let bookmarked = state
+                            .current_path
+                            .as_ref()
+                            .map(|p| state.persistent_settings.is_bookmarked(p))
+                            .unwrap_or_default();
+                        if tooltip(
+                            unframed_button_colored("🔖", bookmarked, ui),
+                            "Bookmark",
+                            &lookup(&state.persistent_settings.shortcuts, &Bookmark),
+                            ui,
+                        )
+                        .clicked()
+                        {
+                            toggle_bookmark(state);
+                        }
+                    }
+
+                    if state.current_path.as_ref().map(|p| p.is_file()) == Some(true)
+                        && tooltip(
+                            unframed_button_colored("◫", state.compare.active, ui),
+                            "Compare with another image",
+                            &lookup(&state.persistent_settings.shortcuts, &Compare),
+                            ui,
+                        )
+                        .clicked()
+                    {
+                        toggle_compare(state);
+                    }
+
+                    if state
+                        .current_path
+                        .as_ref()
+                        .map(|p| p.is_file() && sequence::has_frame_number(p))
+                        == Some(true)
+                        && tooltip(
+                            unframed_button_colored("🎞", state.sequence.is_some(), ui),
+                            "Play the numbered sequence this image belongs to",
+                            &lookup(&state.persistent_settings.shortcuts, &Sequence),
+                            ui,
+                        )
+                        .clicked()
+                    {
+                        toggle_sequence(state);
+                    }
+
+                    if state.current_image.is_some()
+                        && tooltip(
+                            unframed_button_colored("📏", state.measure.is_active(), ui),
+                            "Measure distances and areas",
+                            &lookup(&state.persistent_settings.shortcuts, &Measure),
+                            ui,
+                        )
+                        .clicked()
+                    {
+                        toggle_measure(state);
+                    }
+
+                    ui.menu_button("🕘", |ui| recent_ui(state, ui))
+                        .response
+                        .on_hover_text("Recent images, folders and bookmarks");
+
+                    if state.current_path.as_ref().map(|p| p.is_file()) == Some(true)
+                        && tooltip(
+                            unframed_button("🗑", ui),
+                            "Move to trash",
+                            &lookup(&state.persistent_settings.shortcuts, &Trash),
+                            ui,
+                        )
+                        .clicked()
+                    {
+                        run_file_action(state, file_actions::trash);
+                    }
+
                     if tooltip(
                         unframed_button("⛶", ui),
                         "Full Screen",
@@ -770,6 +1485,13 @@ fn drawe(app: &mut App, gfx: &mut Graphics, plugins: &mut Plugins, state: &mut O
             }
         }
 
+        sequence_ui(ctx, state);
+
+        playback_ui(ctx, state);
+
+        watch_folder_ui(ctx, state);
+        compare_ui(ctx, state);
+
         if state.info_enabled {
             info_ui(ctx, state, gfx);
         }
@@ -778,7 +1500,16 @@ fn drawe(app: &mut App, gfx: &mut Graphics, plugins: &mut Plugins, state: &mut O
             edit_ui(ctx, state, gfx);
         }
 
-        if !state.is_loaded {
+        // The grid takes the remaining space, so it comes after all other panels
+        if state.thumbnail_grid.enabled {
+            thumbnail_grid_ui(ctx, state, gfx);
+        } else {
+            state.thumbnail_grid.thumbnails.release_textures(gfx);
+        }
+
+        minimap_ui(ctx, state, gfx);
+
+        if !state.is_loaded && !state.thumbnail_grid.enabled {
             egui::Window::new("")
                 .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
                 .collapsible(false)
@@ -794,6 +1525,11 @@ fn drawe(app: &mut App, gfx: &mut Graphics, plugins: &mut Plugins, state: &mut O
                                 state.current_path.clone().unwrap_or_default().display()
                             ));
                         });
+                    } else if let Some(dir) = state.watch_folder.dir() {
+                        ui.horizontal(|ui| {
+                            ui.add(egui::Spinner::default());
+                            ui.label(format!("👁 Waiting for images in {}", dir.display()));
+                        });
                     } else {
                         ui.heading("🖼 Please drag an image here!");
                     }
@@ -817,6 +1553,10 @@ fn drawe(app: &mut App, gfx: &mut Graphics, plugins: &mut Plugins, state: &mut O
         } else {
             state.key_grab = false;
         }
+        rename_ui(ctx, state);
+        tags_ui(ctx, state);
+        measure_ui(ctx, state);
+
         // Settings come last, as they block keyboard grab (for hotkey assigment)
         settings_ui(app, ctx, state);
     });
@@ -827,7 +1567,7 @@ fn drawe(app: &mut App, gfx: &mut Graphics, plugins: &mut Plugins, state: &mut O
     // if state.edit_state.is_processing {
     //     app.window().request_frame();
     // }
-    draw.clear(Color::from_rgb(0.2, 0.2, 0.2));
+    draw.clear(state.persistent_settings.background.clear_color());
     gfx.render(&draw);
     gfx.render(&egui_output);
     if egui_output.needs_repaint() {
@@ -840,6 +1580,17 @@ fn drawe(app: &mut App, gfx: &mut Graphics, plugins: &mut Plugins, state: &mut O
 //     window.set_title(title);
 // }
 
+/// The wipe divider currently on screen, if any
+fn active_split(state: &mut OculanteState) -> Option<&mut Split> {
+    if state.edit_enabled && state.before_after.mode == BeforeAfterMode::Wipe {
+        Some(&mut state.before_after.split)
+    } else if state.compare.active && state.compare.mode == CompareMode::Wipe {
+        Some(&mut state.compare.split)
+    } else {
+        None
+    }
+}
+
 fn toggle_fullscreen(app: &mut App, state: &mut OculanteState) {
     let fullscreen = app.window().is_fullscreen();
 
@@ -876,9 +1627,86 @@ fn toggle_fullscreen(app: &mut App, state: &mut OculanteState) {
     app.window().set_fullscreen(!fullscreen);
 }
 
+fn toggle_slideshow(app: &mut App, state: &mut OculanteState) {
+    if state.slideshow.active {
+        state.slideshow.stop();
+        if state.slideshow.entered_fullscreen && app.window().is_fullscreen() {
+            toggle_fullscreen(app, state);
+        }
+        state.slideshow.entered_fullscreen = false;
+    } else {
+        state.slideshow.start();
+        if state.persistent_settings.slideshow.fullscreen && !app.window().is_fullscreen() {
+            toggle_fullscreen(app, state);
+            state.slideshow.entered_fullscreen = true;
+        }
+    }
+}
+
+/// Reload the current image if it was changed on disk, and refresh the folder listing
+fn watch_current_file(state: &mut OculanteState) {
+    let current = match &state.current_path {
+        Some(p) => p.clone(),
+        None => return,
+    };
+    let dir = match current.parent() {
+        Some(dir) if dir.as_os_str().is_empty() => std::path::Path::new("."),
+        Some(dir) => dir,
+        None => return,
+    };
+    state.folder_watcher.watch(dir);
+
+    let changed = state.folder_watcher.poll();
+    if changed.is_empty() {
+        return;
+    }
+    state.folder_listing.invalidate();
+    for path in &changed {
+        state.thumbnail_grid.thumbnails.remove(path);
+    }
+
+    // the watched folder is the current one, so comparing names is enough
+    if changed.iter().any(|p| p.file_name() == current.file_name()) && current.is_file() {
+        if state.edit_enabled {
+            state.message = Some("The image was changed on disk".into());
+        } else {
+            debug!("Reloading {}", current.display());
+            state.keep_view_for = Some(current.clone());
+            state.player.load(&current, state.message_channel.0.clone());
+        }
+    }
+}
+
+fn toggle_thumbnail_grid(state: &mut OculanteState) {
+    state.thumbnail_grid.enabled = !state.thumbnail_grid.enabled;
+    if state.thumbnail_grid.enabled {
+        // start at the image that is currently open
+        let current = state.current_path.clone();
+        if let Some(index) = grid_files(state)
+            .iter()
+            .position(|p| Some(p) == current.as_ref())
+        {
+            state.thumbnail_grid.selected = index;
+        }
+        state.thumbnail_grid.scroll_to_selected = true;
+    }
+}
+
 fn prev_image(state: &mut OculanteState) {
+    // go through the recently arrived images instead of the folder
+    if state.watch_folder.active {
+        if let Some(path) = state.watch_folder.back() {
+            show_watched(state, path);
+        }
+        return;
+    }
     if let Some(img_location) = state.current_path.as_mut() {
-        let next_img = img_shift(&img_location, -1);
+        let next_img = img_shift(
+            &img_location,
+            -1,
+            &mut state.folder_listing,
+            &state.persistent_settings,
+        );
         // prevent reload if at last or first
         if &next_img != img_location {
             state.is_loaded = false;
@@ -891,8 +1719,19 @@ fn prev_image(state: &mut OculanteState) {
 }
 
 fn next_image(state: &mut OculanteState) {
+    if state.watch_folder.active {
+        if let Some(path) = state.watch_folder.forward() {
+            show_watched(state, path);
+        }
+        return;
+    }
     if let Some(img_location) = state.current_path.as_mut() {
-        let next_img = img_shift(&img_location, 1);
+        let next_img = img_shift(
+            &img_location,
+            1,
+            &mut state.folder_listing,
+            &state.persistent_settings,
+        );
         // prevent reload if at last or first
         if &next_img != img_location {
             state.is_loaded = false;
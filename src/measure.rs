@@ -0,0 +1,198 @@
+use nalgebra::Vector2;
+use notan::draw::*;
+use notan::prelude::Color;
+use std::fmt::Write;
+use std::path::Path;
+use strum::Display;
+use strum_macros::EnumIter;
+
+const MM_PER_INCH: f64 = 25.4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, Display)]
+pub enum MeasureMode {
+    Off,
+    Line,
+    Rectangle,
+}
+
+impl Default for MeasureMode {
+    fn default() -> Self {
+        MeasureMode::Off
+    }
+}
+
+/// A distance or area picked on the image, in image pixels
+#[derive(Debug, Default)]
+pub struct Measure {
+    pub mode: MeasureMode,
+    pub start: Option<Vector2<f32>>,
+    pub end: Option<Vector2<f32>>,
+    pub dragging: bool,
+    /// Pixels per inch of the current image, if it says
+    pub dpi: Option<f64>,
+}
+
+/// The middle of the pixel at `pos`
+pub fn snap(pos: Vector2<f32>) -> Vector2<f32> {
+    pos.map(|v| v.floor() + 0.5)
+}
+
+impl Measure {
+    pub fn is_active(&self) -> bool {
+        self.mode != MeasureMode::Off
+    }
+
+    pub fn begin(&mut self, pos: Vector2<f32>) {
+        self.start = Some(snap(pos));
+        self.end = Some(snap(pos));
+        self.dragging = true;
+    }
+
+    pub fn drag(&mut self, pos: Vector2<f32>) {
+        if self.dragging {
+            self.end = Some(snap(pos));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.start = None;
+        self.end = None;
+        self.dragging = false;
+    }
+
+    fn points(&self) -> Option<(Vector2<f32>, Vector2<f32>)> {
+        Some((self.start?, self.end?))
+    }
+
+    /// Labels and values of the current measurement
+    pub fn rows(&self) -> Vec<(&'static str, String)> {
+        let (start, end) = match self.points() {
+            Some(points) => points,
+            None => return vec![],
+        };
+        let (dx, dy) = ((end.x - start.x) as f64, (end.y - start.y) as f64);
+        let mut rows = vec![
+            (
+                "From",
+                format!("{:.0},{:.0}", start.x.floor(), start.y.floor()),
+            ),
+            ("To", format!("{:.0},{:.0}", end.x.floor(), end.y.floor())),
+        ];
+        match self.mode {
+            MeasureMode::Off => (),
+            MeasureMode::Line => {
+                let length = dx.hypot(dy);
+                // counter clockwise from the x axis, as y points down
+                let angle = (-dy).atan2(dx).to_degrees();
+                rows.push(("Length", format!("{length:.2} px")));
+                rows.push(("Angle", format!("{angle:.2}°")));
+                rows.push(("dx, dy", format!("{dx:.0}, {dy:.0}")));
+                if let Some(dpi) = self.dpi {
+                    rows.push(("Physical", physical(length, dpi)));
+                }
+            }
+            MeasureMode::Rectangle => {
+                // both corner pixels are inside
+                let (width, height) = (dx.abs() + 1.0, dy.abs() + 1.0);
+                rows.push(("Size", format!("{width:.0}x{height:.0} px")));
+                rows.push(("Area", format!("{:.0} px²", width * height)));
+                rows.push(("Aspect", format!("{:.4}", width / height)));
+                if let Some(dpi) = self.dpi {
+                    rows.push((
+                        "Physical",
+                        format!("{} x {}", physical(width, dpi), physical(height, dpi)),
+                    ));
+                }
+            }
+        }
+        rows
+    }
+
+    /// The measurement as text, one value per line
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for (label, value) in self.rows() {
+            _ = writeln!(text, "{label}: {value}");
+        }
+        text
+    }
+
+    /// Draw the line or rectangle. `to_screen` maps image positions to the window.
+    pub fn draw(&self, draw: &mut Draw, to_screen: impl Fn(Vector2<f32>) -> Vector2<f32>) {
+        let (start, end) = match self.points() {
+            Some(points) => points,
+            None => return,
+        };
+        let color = Color::from_rgb(1.0, 0.9, 0.0);
+        let mut line = |a: Vector2<f32>, b: Vector2<f32>| {
+            let (a, b) = (to_screen(a), to_screen(b));
+            draw.line((a.x, a.y), (b.x, b.y)).width(1.5).color(color);
+        };
+        match self.mode {
+            MeasureMode::Off => (),
+            MeasureMode::Line => line(start, end),
+            MeasureMode::Rectangle => {
+                // around the corner pixels, not through their middles
+                let min = start.inf(&end).map(|v| v.floor());
+                let max = start.sup(&end).map(|v| v.floor() + 1.0);
+                let corners = [
+                    min,
+                    Vector2::new(max.x, min.y),
+                    max,
+                    Vector2::new(min.x, max.y),
+                ];
+                for i in 0..4 {
+                    line(corners[i], corners[(i + 1) % 4]);
+                }
+            }
+        }
+    }
+}
+
+/// `pixels` in millimeters and inches
+pub fn physical(pixels: f64, dpi: f64) -> String {
+    let inches = pixels / dpi;
+    format!("{:.2} mm / {:.3} in", inches * MM_PER_INCH, inches)
+}
+
+/// Pixels per inch from the EXIF resolution, or the pHYs chunk of a png
+pub fn read_dpi(path: &Path) -> Option<f64> {
+    exif_dpi(path).or_else(|| png_dpi(path))
+}
+
+fn exif_dpi(path: &Path) -> Option<f64> {
+    let file = std::fs::File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut std::io::BufReader::new(&file))
+        .ok()?;
+    let resolution = match &exif
+        .get_field(exif::Tag::XResolution, exif::In::PRIMARY)?
+        .value
+    {
+        exif::Value::Rational(values) => values.first()?.to_f64(),
+        _ => return None,
+    };
+    // 2 is inches, which is also the default, 3 is centimeters
+    let unit = exif
+        .get_field(exif::Tag::ResolutionUnit, exif::In::PRIMARY)
+        .and_then(|f| f.value.get_uint(0))
+        .unwrap_or(2);
+    let dpi = match unit {
+        3 => resolution * 2.54,
+        _ => resolution,
+    };
+    Some(dpi).filter(|dpi| *dpi > 0.0)
+}
+
+fn png_dpi(path: &Path) -> Option<f64> {
+    if path.extension()?.to_str()?.to_lowercase() != "png" {
+        return None;
+    }
+    let file = std::fs::File::open(path).ok()?;
+    let reader = png::Decoder::new(file).read_info().ok()?;
+    let dims = reader.info().pixel_dims?;
+    match dims.unit {
+        png::Unit::Meter if dims.xppu > 0 => Some(dims.xppu as f64 * MM_PER_INCH / 1000.0),
+        _ => None,
+    }
+}
@@ -0,0 +1,29 @@
+use nalgebra::Vector2;
+
+/// Longest side of the minimap in points
+pub const SIZE: f32 = 180.0;
+
+/// Whether part of an image of `view_size`, as rotated on screen, is outside the window
+pub fn is_needed(
+    offset: Vector2<f32>,
+    scale: f32,
+    view_size: Vector2<f32>,
+    window: Vector2<f32>,
+) -> bool {
+    let end = offset + view_size * scale;
+    offset.x < -0.5 || offset.y < -0.5 || end.x > window.x + 0.5 || end.y > window.y + 0.5
+}
+
+/// Top left corner and size of the window in image pixels, as rotated on screen
+pub fn viewport(
+    offset: Vector2<f32>,
+    scale: f32,
+    window: Vector2<f32>,
+) -> (Vector2<f32>, Vector2<f32>) {
+    (-offset / scale, window / scale)
+}
+
+/// The offset showing `pos`, in image pixels as rotated on screen, in the middle of the window
+pub fn center_on(pos: Vector2<f32>, scale: f32, window: Vector2<f32>) -> Vector2<f32> {
+    window / 2.0 - pos * scale
+}
@@ -0,0 +1,352 @@
+use log::{debug, error};
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use strum::Display;
+use strum_macros::EnumIter;
+
+use crate::utils::{is_ext_compatible, open_image};
+use crate::xmp;
+
+/// How files in a folder are ordered when navigating
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumIter, Display)]
+pub enum SortOrder {
+    /// File name, with numbers compared by value
+    Name,
+    Modified,
+    Size,
+    Dimensions,
+    /// EXIF capture time
+    CaptureDate,
+    Shuffle,
+}
+
+impl Default for SortOrder {
+    fn default() -> Self {
+        SortOrder::Name
+    }
+}
+
+/// Which images are included when navigating
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileFilter {
+    /// Part of the file name, ignoring case
+    pub name: String,
+    /// Minimum star rating from the xmp sidecar
+    pub min_rating: u8,
+    /// Tag the image must have
+    pub tag: String,
+}
+
+impl FileFilter {
+    fn matches(&self, path: &Path) -> bool {
+        let name = self.name.to_lowercase();
+        if !name.is_empty()
+            && !path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_lowercase().contains(&name))
+                .unwrap_or_default()
+        {
+            return false;
+        }
+        // only read sidecars if needed
+        if self.min_rating == 0 && self.tag.is_empty() {
+            return true;
+        }
+        let data = xmp::read(path);
+        data.rating >= self.min_rating as i8
+            && (self.tag.is_empty()
+                || data
+                    .tags
+                    .iter()
+                    .any(|t| t.eq_ignore_ascii_case(self.tag.trim())))
+    }
+}
+
+/// Everything that changes the file order
+#[derive(Debug, Clone, PartialEq)]
+struct ListingKey {
+    dir: PathBuf,
+    dir_modified: Option<SystemTime>,
+    sort: SortOrder,
+    descending: bool,
+    filter: FileFilter,
+    seed: u64,
+}
+
+/// Sort keys that need the files to be read, with the modification time they were read at
+#[derive(Debug, Default)]
+pub struct SortKeys {
+    pixels: HashMap<PathBuf, (Option<SystemTime>, u64)>,
+    capture_dates: HashMap<PathBuf, (Option<SystemTime>, Option<String>)>,
+}
+
+/// The keys of `files`, reading the ones that are unknown or changed in parallel
+fn cached_keys<K: Clone + Send + Sync>(
+    cache: &mut HashMap<PathBuf, (Option<SystemTime>, K)>,
+    files: &[PathBuf],
+    read: impl Fn(&Path) -> K + Sync,
+) -> Vec<K> {
+    let known = &*cache;
+    let keys = files
+        .par_iter()
+        .map(|p| {
+            let modified = modified(p);
+            match known.get(p) {
+                Some((read_at, key)) if *read_at == modified => (modified, key.clone(), false),
+                _ => (modified, read(p), true),
+            }
+        })
+        .collect::<Vec<_>>();
+    for (path, (modified, key, fresh)) in files.iter().zip(&keys) {
+        if *fresh {
+            cache.insert(path.clone(), (*modified, key.clone()));
+        }
+    }
+    keys.into_iter().map(|(_, key, _)| key).collect()
+}
+
+/// The sorted and filtered images of a folder. Kept around so the folder does not need to be
+/// read and sorted again on every image change.
+#[derive(Debug)]
+pub struct FolderListing {
+    key: Option<ListingKey>,
+    files: Vec<PathBuf>,
+    /// Seed for the shuffle order
+    seed: u64,
+    sort_keys: SortKeys,
+}
+
+impl Default for FolderListing {
+    fn default() -> Self {
+        Self {
+            key: None,
+            files: vec![],
+            seed: thread_rng().gen(),
+            sort_keys: Default::default(),
+        }
+    }
+}
+
+impl FolderListing {
+    /// The images in the folder of `file`, updated if the folder or the options changed
+    pub fn files(
+        &mut self,
+        file: &Path,
+        sort: SortOrder,
+        descending: bool,
+        filter: &FileFilter,
+    ) -> &[PathBuf] {
+        let dir = file.parent().unwrap_or(Path::new(".")).to_path_buf();
+        let key = ListingKey {
+            dir_modified: std::fs::metadata(&dir).and_then(|m| m.modified()).ok(),
+            dir,
+            sort,
+            descending,
+            filter: filter.clone(),
+            seed: self.seed,
+        };
+        if self.key.as_ref() != Some(&key) {
+            self.files = list_folder_cached(
+                &key.dir,
+                sort,
+                descending,
+                &key.filter,
+                key.seed,
+                &mut self.sort_keys,
+            );
+            self.key = Some(key);
+        }
+        &self.files
+    }
+
+    /// Read the folder again next time, for example because files changed
+    pub fn invalidate(&mut self) {
+        self.key = None;
+    }
+
+    /// Use a new random order for `SortOrder::Shuffle`
+    pub fn reshuffle(&mut self) {
+        self.seed = thread_rng().gen();
+    }
+}
+
+/// Read, filter and sort all compatible images in `dir`
+pub fn list_folder(
+    dir: &Path,
+    sort: SortOrder,
+    descending: bool,
+    filter: &FileFilter,
+    seed: u64,
+) -> Vec<PathBuf> {
+    list_folder_cached(dir, sort, descending, filter, seed, &mut Default::default())
+}
+
+/// Like `list_folder`, reusing sort keys of files that did not change since they were read
+pub fn list_folder_cached(
+    dir: &Path,
+    sort: SortOrder,
+    descending: bool,
+    filter: &FileFilter,
+    seed: u64,
+    sort_keys: &mut SortKeys,
+) -> Vec<PathBuf> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Can't read {}: {e}", dir.display());
+            return vec![];
+        }
+    };
+
+    let mut files = entries
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry.path()),
+            Err(e) => {
+                error!("Skipping unreadable entry in {}: {e}", dir.display());
+                None
+            }
+        })
+        .filter(|p| is_ext_compatible(p) && filter.matches(p))
+        .collect::<Vec<PathBuf>>();
+
+    debug!("Sorting {} files by {sort}", files.len());
+    sort_files(&mut files, sort, seed, sort_keys);
+    if descending {
+        files.reverse();
+    }
+    files
+}
+
+fn sort_files(files: &mut Vec<PathBuf>, sort: SortOrder, seed: u64, sort_keys: &mut SortKeys) {
+    // Sort by name first, so files with equal keys stay in a predictable order
+    files.sort_by(|a, b| natural_cmp(&file_name(a), &file_name(b)));
+
+    match sort {
+        SortOrder::Name => (),
+        SortOrder::Modified => files.sort_by_cached_key(|p| modified(p)),
+        SortOrder::Size => {
+            files.sort_by_cached_key(|p| std::fs::metadata(p).map(|m| m.len()).unwrap_or_default())
+        }
+        SortOrder::Dimensions => {
+            let keys = cached_keys(&mut sort_keys.pixels, files, pixel_count);
+            sort_by_keys(files, keys);
+        }
+        // Images without capture date come first, ordered by modification date
+        SortOrder::CaptureDate => {
+            let dates = cached_keys(&mut sort_keys.capture_dates, files, capture_date);
+            let keys = dates
+                .into_iter()
+                .zip(files.iter())
+                .map(|(date, p)| (date, modified(p)))
+                .collect();
+            sort_by_keys(files, keys);
+        }
+        SortOrder::Shuffle => files.shuffle(&mut ChaCha8Rng::seed_from_u64(seed)),
+    }
+}
+
+/// Stable sort of `files` by the key at the same position
+fn sort_by_keys<K: Ord>(files: &mut Vec<PathBuf>, keys: Vec<K>) {
+    let mut keyed = keys.into_iter().zip(files.drain(..)).collect::<Vec<_>>();
+    keyed.sort_by(|a, b| a.0.cmp(&b.0));
+    files.extend(keyed.into_iter().map(|(_, p)| p));
+}
+
+/// Number of pixels of an image. Formats the image crate can't read the header of
+/// are decoded completely.
+fn pixel_count(path: &Path) -> u64 {
+    let (width, height) = image::image_dimensions(path)
+        .ok()
+        .or_else(|| {
+            let frame = open_image(&path.to_path_buf())
+                .ok()?
+                .frames
+                .into_iter()
+                .next()?;
+            Some(frame.buffer.dimensions())
+        })
+        .unwrap_or_default();
+    width as u64 * height as u64
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// The EXIF capture date of an image. These look like "2023:01:31 12:00:00",
+/// so comparing them as text works.
+fn capture_date(path: &Path) -> Option<String> {
+    let file = std::fs::File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut std::io::BufReader::new(&file))
+        .ok()?;
+    let field = exif
+        .get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)
+        .or_else(|| exif.get_field(exif::Tag::DateTime, exif::In::PRIMARY))?;
+    match &field.value {
+        exif::Value::Ascii(values) => values
+            .first()
+            .map(|v| String::from_utf8_lossy(v).trim().to_string()),
+        _ => None,
+    }
+}
+
+/// Compare strings so that numbers are ordered by value: `img_2` comes before `img_10`
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let mut num_a = String::new();
+                while let Some(c) = a.peek().copied().filter(|c| c.is_ascii_digit()) {
+                    num_a.push(c);
+                    a.next();
+                }
+                let mut num_b = String::new();
+                while let Some(c) = b.peek().copied().filter(|c| c.is_ascii_digit()) {
+                    num_b.push(c);
+                    b.next();
+                }
+                // compare by value without overflowing: fewer significant digits is smaller
+                let trimmed_a = num_a.trim_start_matches('0');
+                let trimmed_b = num_b.trim_start_matches('0');
+                let ordering = trimmed_a
+                    .len()
+                    .cmp(&trimmed_b.len())
+                    .then_with(|| trimmed_a.cmp(trimmed_b))
+                    .then_with(|| num_a.len().cmp(&num_b.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x
+                    .to_lowercase()
+                    .cmp(y.to_lowercase())
+                    .then_with(|| x.cmp(&y));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use nalgebra::Vector2;
+use notan::math::{Mat3, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::compare::BeforeAfterMode;
+use crate::utils::{ImageExt, OculanteState};
+
+/// Rotation and mirroring of the view, leaving the image itself alone.
+/// The image is rotated first, mirroring then flips what is on screen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Orientation {
+    /// Clockwise quarter turns
+    pub rotation: u8,
+    pub mirror_horizontal: bool,
+    pub mirror_vertical: bool,
+}
+
+impl Orientation {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn rotate(&mut self, clockwise: bool) {
+        self.rotation = (self.rotation + if clockwise { 1 } else { 3 }) % 4;
+    }
+
+    /// Whether width and height are swapped on screen
+    pub fn is_transposed(&self) -> bool {
+        self.rotation % 2 == 1
+    }
+
+    /// Size on screen at 100% of an image of `size`
+    pub fn view_size(&self, size: Vector2<f32>) -> Vector2<f32> {
+        if self.is_transposed() {
+            Vector2::new(size.y, size.x)
+        } else {
+            size
+        }
+    }
+
+    /// Position on screen, relative to the top left corner at 100%, of position `p`
+    /// in an image of `size`
+    pub fn to_view(&self, p: Vector2<f32>, size: Vector2<f32>) -> Vector2<f32> {
+        let (w, h) = (size.x, size.y);
+        let mut v = match self.rotation % 4 {
+            1 => Vector2::new(h - p.y, p.x),
+            2 => Vector2::new(w - p.x, h - p.y),
+            3 => Vector2::new(p.y, w - p.x),
+            _ => p,
+        };
+        let view = self.view_size(size);
+        if self.mirror_horizontal {
+            v.x = view.x - v.x;
+        }
+        if self.mirror_vertical {
+            v.y = view.y - v.y;
+        }
+        v
+    }
+
+    /// The inverse of `to_view`: the position in the image shown at `v`
+    pub fn to_image(&self, v: Vector2<f32>, size: Vector2<f32>) -> Vector2<f32> {
+        let (w, h) = (size.x, size.y);
+        let view = self.view_size(size);
+        let mut v = v;
+        if self.mirror_horizontal {
+            v.x = view.x - v.x;
+        }
+        if self.mirror_vertical {
+            v.y = view.y - v.y;
+        }
+        match self.rotation % 4 {
+            1 => Vector2::new(v.y, h - v.x),
+            2 => Vector2::new(w - v.x, h - v.y),
+            3 => Vector2::new(w - v.y, v.x),
+            _ => v,
+        }
+    }
+
+    /// Transform drawing an image of `size` at `offset` and `scale` in this orientation
+    pub fn matrix(&self, size: Vector2<f32>, offset: Vector2<f32>, scale: f32) -> Mat3 {
+        let origin = self.to_view(Vector2::zeros(), size);
+        let x_axis = (self.to_view(Vector2::new(1.0, 0.0), size) - origin) * scale;
+        let y_axis = (self.to_view(Vector2::new(0.0, 1.0), size) - origin) * scale;
+        let translation = offset + origin * scale;
+        Mat3::from_cols(
+            Vec3::new(x_axis.x, x_axis.y, 0.0),
+            Vec3::new(y_axis.x, y_axis.y, 0.0),
+            Vec3::new(translation.x, translation.y, 1.0),
+        )
+    }
+}
+
+/// The orientation the current image is drawn with. Compared images and the
+/// before/after view are shown unrotated, so they line up with it.
+pub fn current(state: &OculanteState) -> Orientation {
+    let before_after = state.edit_enabled && state.before_after.mode != BeforeAfterMode::Off;
+    if state.compare.active || before_after {
+        Orientation::default()
+    } else {
+        state.orientation
+    }
+}
+
+/// Show the current image in `orientation`, keeping its center in place
+pub fn set(state: &mut OculanteState, orientation: Orientation) {
+    let size = state.current_image.as_ref().map(|img| img.size_vec());
+    if let Some(size) = size {
+        let center = state.offset + state.orientation.view_size(size) * state.scale / 2.0;
+        state.offset = center - orientation.view_size(size) * state.scale / 2.0;
+    }
+    state.orientation = orientation;
+    if let Some(path) = &state.current_path {
+        state
+            .persistent_settings
+            .remember_orientation(path, orientation);
+    }
+}
@@ -0,0 +1,59 @@
+use nalgebra::Vector2;
+use notan::prelude::TextureFilter;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// Zoom from which the RGBA values of a pixel fit inside it
+pub const VALUES_ZOOM: f32 = 32.0;
+
+/// How single pixels are shown when zoomed in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PixelGridSettings {
+    /// Show crisp pixels instead of interpolating when magnified
+    pub nearest: bool,
+    pub grid: bool,
+    /// Zoom from which the grid is shown
+    pub grid_zoom: f32,
+    /// Write the RGBA values into each pixel at extreme zoom
+    pub values: bool,
+}
+
+impl Default for PixelGridSettings {
+    fn default() -> Self {
+        Self {
+            nearest: true,
+            grid: true,
+            grid_zoom: 8.0,
+            values: false,
+        }
+    }
+}
+
+impl PixelGridSettings {
+    /// Filter for textures drawn larger than their size
+    pub fn mag_filter(&self) -> TextureFilter {
+        if self.nearest {
+            TextureFilter::Nearest
+        } else {
+            TextureFilter::Linear
+        }
+    }
+}
+
+/// Columns and rows of an image of `size` that are at least partly inside the window
+pub fn visible_pixels(
+    offset: Vector2<f32>,
+    scale: f32,
+    size: (u32, u32),
+    window: Vector2<f32>,
+) -> (Range<u32>, Range<u32>) {
+    let range = |offset: f32, extent: f32, len: u32| {
+        let start = (-offset / scale).floor().clamp(0.0, len as f32) as u32;
+        let end = ((extent - offset) / scale).ceil().clamp(0.0, len as f32) as u32;
+        start..end.max(start)
+    };
+    (
+        range(offset.x, window.x, size.0),
+        range(offset.y, window.y, size.1),
+    )
+}
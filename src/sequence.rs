@@ -0,0 +1,191 @@
+use anyhow::{anyhow, Result};
+use log::{debug, error};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+
+use crate::utils::{open_image, AnimationSource, Frame, FrameSource, FrameStore};
+
+/// How much memory decoded sequence frames may use
+const MAX_CACHE_BYTES: usize = 1 << 30;
+
+/// Gaps listed in a summary before it is cut off
+const MAX_LISTED_GAPS: usize = 10;
+
+/// Files in a directory that only differ by a frame number, like `shot_0001.exr`
+#[derive(Debug, Clone)]
+pub struct ImageSequence {
+    /// Frame numbers and their files, sorted by number
+    pub files: Vec<(u64, PathBuf)>,
+    /// Frame numbers between the first and last frame which have no file
+    pub missing: Vec<u64>,
+}
+
+impl ImageSequence {
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.files.iter().map(|(_, p)| p.clone()).collect()
+    }
+
+    /// Describe missing frames, merging consecutive numbers into ranges
+    pub fn missing_summary(&self) -> String {
+        let mut ranges: Vec<(u64, u64)> = vec![];
+        for &n in &self.missing {
+            match ranges.last_mut() {
+                Some(range) if range.1 + 1 == n => range.1 = n,
+                _ => ranges.push((n, n)),
+            }
+        }
+        let mut summary = ranges
+            .iter()
+            .take(MAX_LISTED_GAPS)
+            .map(|(a, b)| {
+                if a == b {
+                    a.to_string()
+                } else {
+                    format!("{a}-{b}")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        if ranges.len() > MAX_LISTED_GAPS {
+            summary.push_str(", …");
+        }
+        summary
+    }
+}
+
+/// Split a file stem into a prefix and trailing frame number digits
+fn split_frame_number(stem: &str) -> Option<(&str, &str)> {
+    let prefix = stem.trim_end_matches(|c: char| c.is_ascii_digit());
+    let digits = &stem[prefix.len()..];
+    if digits.is_empty() {
+        None
+    } else {
+        Some((prefix, digits))
+    }
+}
+
+/// Whether the name of `path` ends with a number, so it could be part of a sequence
+pub fn has_frame_number(path: &Path) -> bool {
+    path.file_stem()
+        .and_then(|s| split_frame_number(&s.to_string_lossy()).map(|_| ()))
+        .is_some()
+}
+
+/// Find the sequence `path` belongs to. Returns `None` if it has no frame number
+/// or no sibling frames.
+pub fn detect_sequence(path: &Path) -> Option<ImageSequence> {
+    let ext = path.extension()?.to_string_lossy().to_lowercase();
+    let stem = path.file_stem()?.to_string_lossy().to_string();
+    let (prefix, digits) = split_frame_number(&stem)?;
+    // zero padded numbers must keep their width, so shot_01 and shot_001 are not mixed
+    let padded = digits.len() > 1 && digits.starts_with('0');
+
+    let mut files = vec![];
+    for entry in std::fs::read_dir(path.parent()?).ok()?.flatten() {
+        let p = entry.path();
+        if p.extension().map(|e| e.to_string_lossy().to_lowercase()) != Some(ext.clone()) {
+            continue;
+        }
+        let other_stem = match p.file_stem() {
+            Some(s) => s.to_string_lossy().to_string(),
+            None => continue,
+        };
+        if let Some((other_prefix, other_digits)) = split_frame_number(&other_stem) {
+            if other_prefix != prefix || (padded && other_digits.len() != digits.len()) {
+                continue;
+            }
+            if let Ok(number) = other_digits.parse::<u64>() {
+                files.push((number, p));
+            }
+        }
+    }
+
+    if files.len() < 2 {
+        return None;
+    }
+    files.sort_by_key(|(n, _)| *n);
+    files.dedup_by_key(|(n, _)| *n);
+
+    let mut missing = vec![];
+    for pair in files.windows(2) {
+        missing.extend(pair[0].0 + 1..pair[1].0);
+    }
+
+    debug!(
+        "Found sequence of {} frames, {} missing",
+        files.len(),
+        missing.len()
+    );
+    Some(ImageSequence { files, missing })
+}
+
+/// How long each frame is shown at `fps` frames per second, in milliseconds
+pub fn frame_delay(fps: f32) -> u16 {
+    (1000. / fps.max(0.1)).round().min(u16::MAX as f32) as u16
+}
+
+/// The first image of `path` as sequence frame
+pub fn decode_frame(path: &Path, delay: u16) -> Result<Frame> {
+    let buffer = open_image(&path.to_path_buf())?
+        .frames
+        .into_iter()
+        .next()
+        .ok_or(anyhow!("{} contains no image", path.display()))?
+        .buffer;
+    Ok(Frame::new(buffer, delay, FrameSource::Animation))
+}
+
+/// Decodes sequence frames on demand and keeps as many as the memory budget allows
+pub struct FrameCache {
+    files: Vec<PathBuf>,
+    /// How long each frame is shown
+    delay: u16,
+    frames: FrameStore,
+    message_sender: Sender<String>,
+}
+
+impl FrameCache {
+    pub fn new(files: Vec<PathBuf>, delay: u16, message_sender: Sender<String>) -> Self {
+        Self {
+            files,
+            delay,
+            frames: FrameStore::new(MAX_CACHE_BYTES),
+            message_sender,
+        }
+    }
+
+    fn decode(&self, index: usize) -> Result<Frame> {
+        let path = self
+            .files
+            .get(index)
+            .ok_or(anyhow!("Sequence has no frame {index}"))?;
+        decode_frame(path, self.delay)
+    }
+}
+
+impl AnimationSource for FrameCache {
+    fn frame(&mut self, index: usize) -> Option<Frame> {
+        if let Some(frame) = self.frames.get(index) {
+            return Some(frame);
+        }
+        match self.decode(index) {
+            Ok(frame) => {
+                self.frames.insert(index, frame.clone());
+                Some(frame)
+            }
+            Err(e) => {
+                error!("{e}");
+                _ = self.message_sender.send(e.to_string());
+                None
+            }
+        }
+    }
+
+    fn frame_count(&self) -> usize {
+        self.files.len()
+    }
+
+    fn delay(&self, _index: usize) -> u16 {
+        self.delay
+    }
+}
@@ -0,0 +1,89 @@
+use log::{debug, error};
+use nalgebra::Vector2;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::utils::{Channel, OculanteState};
+
+/// How many recent images and folders are kept
+pub const RECENT_LEN: usize = 15;
+
+/// What was on screen when oculante was closed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub path: PathBuf,
+    pub scale: f32,
+    pub offset: [f32; 2],
+    pub channel: Channel,
+    pub info_enabled: bool,
+    pub edit_enabled: bool,
+    pub always_on_top: bool,
+}
+
+impl Session {
+    /// The current view, if an image file is shown
+    pub fn from_state(state: &OculanteState) -> Option<Self> {
+        let path = state.current_path.as_ref().filter(|p| p.is_file())?;
+        Some(Session {
+            path: absolute(path),
+            scale: state.scale,
+            offset: [state.offset.x, state.offset.y],
+            channel: state.current_channel,
+            info_enabled: state.info_enabled,
+            edit_enabled: state.edit_enabled,
+            always_on_top: state.always_on_top,
+        })
+    }
+
+    /// Open the image again with the same view. The window state is up to the caller.
+    pub fn restore(&self, state: &mut OculanteState) {
+        if !self.path.is_file() {
+            debug!("{} is gone, not restoring session", self.path.display());
+            return;
+        }
+        state.scale = self.scale;
+        state.offset = Vector2::new(self.offset[0], self.offset[1]);
+        state.current_channel = self.channel;
+        state.info_enabled = self.info_enabled;
+        state.edit_enabled = self.edit_enabled;
+        state.always_on_top = self.always_on_top;
+        state.keep_view_for = Some(self.path.clone());
+        state
+            .player
+            .load(&self.path, state.message_channel.0.clone());
+        state.current_path = Some(self.path.clone());
+    }
+}
+
+/// Store the recent images and, if enabled, the session for the next start
+pub fn save(state: &mut OculanteState) {
+    state.persistent_settings.session = if state.persistent_settings.restore_session {
+        Session::from_state(state)
+    } else {
+        None
+    };
+    if let Err(e) = state.persistent_settings.save() {
+        error!("Can't save session: {e}");
+    }
+}
+
+/// Full path of a file, so it can be opened from anywhere later
+pub fn absolute(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Put `path` first, removing it further down and dropping the oldest entries
+pub fn push_recent(recent: &mut Vec<PathBuf>, path: &Path) {
+    recent.retain(|p| p != path);
+    recent.insert(0, path.to_path_buf());
+    recent.truncate(RECENT_LEN);
+}
+
+/// Remember an image that was shown. Browsing a folder replaces the previous image
+/// of that folder, so the list holds the last image seen in each place.
+pub fn push_recent_file(recent: &mut Vec<PathBuf>, path: &Path) {
+    if recent.first().and_then(|p| p.parent()) == path.parent() {
+        recent.remove(0);
+    }
+    push_recent(recent, path);
+}
@@ -1,7 +1,16 @@
+use crate::background::BackgroundSettings;
+use crate::navigation::{FileFilter, SortOrder};
+use crate::orientation::Orientation;
+use crate::pixel_grid::PixelGridSettings;
+use crate::session::{self, Session};
 use crate::shortcuts::*;
+use crate::slideshow::SlideshowSettings;
+use crate::zoom::FitMode;
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs::File;
+use std::path::{Path, PathBuf};
 
 // #[serde_as]
 #[derive(Debug, Serialize, Deserialize)]
@@ -9,6 +18,61 @@ pub struct PersistentSettings {
     pub accent_color: [u8; 3],
     pub vsync: bool,
     pub shortcuts: Shortcuts,
+    /// Order of images when navigating a folder
+    #[serde(default)]
+    pub sort_order: SortOrder,
+    #[serde(default)]
+    pub sort_descending: bool,
+    /// Only navigate to images whose name contains this
+    #[serde(default)]
+    pub name_filter: String,
+    #[serde(default)]
+    pub slideshow: SlideshowSettings,
+    /// Folders images can be moved or copied to with the number keys
+    #[serde(default)]
+    pub quick_folders: Vec<PathBuf>,
+    /// Only navigate to images with at least this many stars
+    #[serde(default)]
+    pub min_rating: u8,
+    /// Only navigate to images with this tag
+    #[serde(default)]
+    pub tag_filter: String,
+    /// Reload the current image when it changes on disk
+    #[serde(default = "default_true")]
+    pub auto_reload: bool,
+    /// Recently shown images, newest first
+    #[serde(default)]
+    pub recent_files: Vec<PathBuf>,
+    /// Folders of recently shown images, newest first
+    #[serde(default)]
+    pub recent_dirs: Vec<PathBuf>,
+    #[serde(default)]
+    pub bookmarks: Vec<PathBuf>,
+    /// Show the last image again on startup, with the same view
+    #[serde(default)]
+    pub restore_session: bool,
+    #[serde(default)]
+    pub session: Option<Session>,
+    /// Solid color or checkerboard behind the image
+    #[serde(default)]
+    pub background: BackgroundSettings,
+    #[serde(default)]
+    pub pixel_grid: PixelGridSettings,
+    /// How new images are scaled to the window
+    #[serde(default)]
+    pub fit_mode: FitMode,
+    /// Keep the view rotation and mirroring of each image
+    #[serde(default)]
+    pub keep_orientation: bool,
+    #[serde(default)]
+    pub orientations: HashMap<PathBuf, Orientation>,
+    /// Show an overview of images that do not fit the window
+    #[serde(default = "default_true")]
+    pub minimap: bool,
+}
+
+fn default_true() -> bool {
+    true
 }
 
 impl Default for PersistentSettings {
@@ -17,15 +81,102 @@ impl Default for PersistentSettings {
             accent_color: [255, 0, 75],
             vsync: true,
             shortcuts: Shortcuts::default_keys(),
+            sort_order: SortOrder::default(),
+            sort_descending: false,
+            name_filter: String::new(),
+            slideshow: Default::default(),
+            quick_folders: vec![],
+            min_rating: 0,
+            tag_filter: String::new(),
+            auto_reload: true,
+            recent_files: vec![],
+            recent_dirs: vec![],
+            bookmarks: vec![],
+            restore_session: false,
+            session: None,
+            background: Default::default(),
+            pixel_grid: Default::default(),
+            fit_mode: FitMode::default(),
+            keep_orientation: false,
+            orientations: HashMap::new(),
+            minimap: true,
         }
     }
 }
 
 impl PersistentSettings {
+    /// Which images to include when navigating
+    pub fn file_filter(&self) -> FileFilter {
+        FileFilter {
+            name: self.name_filter.clone(),
+            min_rating: self.min_rating,
+            tag: self.tag_filter.clone(),
+        }
+    }
+
+    /// Add an image and its folder to the recently used ones
+    pub fn add_recent(&mut self, path: &Path) {
+        let path = &session::absolute(path);
+        session::push_recent_file(&mut self.recent_files, path);
+        if let Some(dir) = path.parent() {
+            session::push_recent(&mut self.recent_dirs, dir);
+        }
+    }
+
+    pub fn is_bookmarked(&self, path: &Path) -> bool {
+        let path = session::absolute(path);
+        self.bookmarks.iter().any(|p| *p == path)
+    }
+
+    /// Add or remove a bookmark. Returns whether `path` is bookmarked now.
+    pub fn toggle_bookmark(&mut self, path: &Path) -> bool {
+        let path = session::absolute(path);
+        if self.is_bookmarked(&path) {
+            self.bookmarks.retain(|p| *p != path);
+            false
+        } else {
+            self.bookmarks.push(path);
+            true
+        }
+    }
+
+    /// How `path` was shown last time, if orientations are kept
+    pub fn orientation(&self, path: &Path) -> Orientation {
+        if !self.keep_orientation {
+            return Orientation::default();
+        }
+        self.orientations
+            .get(&session::absolute(path))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn remember_orientation(&mut self, path: &Path, orientation: Orientation) {
+        if !self.keep_orientation {
+            return;
+        }
+        let path = session::absolute(path);
+        if orientation.is_identity() {
+            self.orientations.remove(&path);
+        } else {
+            self.orientations.insert(path, orientation);
+        }
+        _ = self.save();
+    }
+
     pub fn load() -> Result<Self> {
         let local_dir = dirs::data_local_dir().ok_or(anyhow!("Can't get local dir"))?;
         let f = File::open(local_dir.join(".oculante"))?;
-        Ok(serde_json::from_reader::<_, PersistentSettings>(f)?)
+        Self::from_reader(f)
+    }
+
+    /// Read settings, giving commands added since they were saved their default keys
+    pub fn from_reader<R: std::io::Read>(reader: R) -> Result<Self> {
+        let mut settings = serde_json::from_reader::<_, PersistentSettings>(reader)?;
+        for (event, keys) in Shortcuts::default_keys() {
+            settings.shortcuts.entry(event).or_insert(keys);
+        }
+        Ok(settings)
     }
 
     pub fn save(&self) -> Result<()> {
@@ -3,6 +3,7 @@ use std::collections::{HashMap, HashSet};
 use log::{debug, error};
 // use std::collections::HashMap;
 
+use crate::file_actions::QUICK_FOLDERS;
 use crate::utils::OculanteState;
 use notan::prelude::App;
 use serde::{Deserialize, Serialize};
@@ -31,8 +32,106 @@ pub enum InputEvent {
     PanRight,
     PanUp,
     PanDown,
+    PauseAnimation,
+    NextFrame,
+    PreviousFrame,
+    Sequence,
+    Slideshow,
+    ThumbnailGrid,
+    SelectionUp,
+    SelectionDown,
+    OpenSelection,
+    Trash,
+    Rename,
+    UndoFileAction,
+    MoveToFolder1,
+    MoveToFolder2,
+    MoveToFolder3,
+    MoveToFolder4,
+    MoveToFolder5,
+    CopyToFolder1,
+    CopyToFolder2,
+    CopyToFolder3,
+    CopyToFolder4,
+    CopyToFolder5,
+    Rate0,
+    Rate1,
+    Rate2,
+    Rate3,
+    Rate4,
+    Rate5,
+    LabelRed,
+    LabelYellow,
+    LabelGreen,
+    LabelBlue,
+    LabelPurple,
+    EditTags,
+    Bookmark,
+    Compare,
+    Flicker,
+    Background,
+    NearestFilter,
+    PixelGrid,
+    Zoom100,
+    Zoom200,
+    Zoom400,
+    Zoom800,
+    FitWidth,
+    FitHeight,
+    FillWindow,
+    RotateClockwise,
+    RotateCounterClockwise,
+    MirrorHorizontal,
+    MirrorVertical,
+    Minimap,
+    Measure,
 }
 
+/// Events moving the current image to the numbered quick folders
+pub const MOVE_TO_FOLDER: [InputEvent; QUICK_FOLDERS] = [
+    InputEvent::MoveToFolder1,
+    InputEvent::MoveToFolder2,
+    InputEvent::MoveToFolder3,
+    InputEvent::MoveToFolder4,
+    InputEvent::MoveToFolder5,
+];
+
+/// Events copying the current image to the numbered quick folders
+pub const COPY_TO_FOLDER: [InputEvent; QUICK_FOLDERS] = [
+    InputEvent::CopyToFolder1,
+    InputEvent::CopyToFolder2,
+    InputEvent::CopyToFolder3,
+    InputEvent::CopyToFolder4,
+    InputEvent::CopyToFolder5,
+];
+
+/// Events setting the star rating, from 0 to 5 stars
+pub const RATE: [InputEvent; 6] = [
+    InputEvent::Rate0,
+    InputEvent::Rate1,
+    InputEvent::Rate2,
+    InputEvent::Rate3,
+    InputEvent::Rate4,
+    InputEvent::Rate5,
+];
+
+/// Events toggling the color labels, in the order of `ColorLabel`
+pub const LABEL: [InputEvent; 5] = [
+    InputEvent::LabelRed,
+    InputEvent::LabelYellow,
+    InputEvent::LabelGreen,
+    InputEvent::LabelBlue,
+    InputEvent::LabelPurple,
+];
+
+/// Events zooming to the levels of `zoom::ZOOM_LEVELS`
+pub const ZOOM: [InputEvent; 4] = [
+    InputEvent::Zoom100,
+    InputEvent::Zoom200,
+    InputEvent::Zoom400,
+    InputEvent::Zoom800,
+];
+
 pub type Shortcuts = HashMap<InputEvent, SimultaneousKeypresses>;
 
 pub type SimultaneousKeypresses = HashSet<String>;
@@ -105,6 +204,59 @@ impl ShortcutExt for Shortcuts {
             .add_key(InputEvent::PreviousImage, "Left")
             .add_key(InputEvent::NextImage, "Right")
             .add_key(InputEvent::ZoomOut, "Minus")
+            .add_key(InputEvent::PauseAnimation, "Space")
+            .add_key(InputEvent::NextFrame, "Period")
+            .add_key(InputEvent::PreviousFrame, "Comma")
+            .add_key(InputEvent::Sequence, "J")
+            .add_key(InputEvent::Slideshow, "S")
+            .add_key(InputEvent::ThumbnailGrid, "Tab")
+            .add_key(InputEvent::SelectionUp, "Up")
+            .add_key(InputEvent::SelectionDown, "Down")
+            .add_key(InputEvent::OpenSelection, "Return")
+            .add_key(InputEvent::Trash, "Delete")
+            .add_key(InputEvent::Rename, "F2")
+            .add_keys(InputEvent::UndoFileAction, &["LControl", "Z"])
+            .add_key(InputEvent::MoveToFolder1, "Key1")
+            .add_key(InputEvent::MoveToFolder2, "Key2")
+            .add_key(InputEvent::MoveToFolder3, "Key3")
+            .add_key(InputEvent::MoveToFolder4, "Key4")
+            .add_key(InputEvent::MoveToFolder5, "Key5")
+            .add_keys(InputEvent::CopyToFolder1, &["LShift", "Key1"])
+            .add_keys(InputEvent::CopyToFolder2, &["LShift", "Key2"])
+            .add_keys(InputEvent::CopyToFolder3, &["LShift", "Key3"])
+            .add_keys(InputEvent::CopyToFolder4, &["LShift", "Key4"])
+            .add_keys(InputEvent::CopyToFolder5, &["LShift", "Key5"])
+            .add_keys(InputEvent::Rate0, &["LControl", "Key0"])
+            .add_keys(InputEvent::Rate1, &["LControl", "Key1"])
+            .add_keys(InputEvent::Rate2, &["LControl", "Key2"])
+            .add_keys(InputEvent::Rate3, &["LControl", "Key3"])
+            .add_keys(InputEvent::Rate4, &["LControl", "Key4"])
+            .add_keys(InputEvent::Rate5, &["LControl", "Key5"])
+            .add_keys(InputEvent::LabelRed, &["LAlt", "Key1"])
+            .add_keys(InputEvent::LabelYellow, &["LAlt", "Key2"])
+            .add_keys(InputEvent::LabelGreen, &["LAlt", "Key3"])
+            .add_keys(InputEvent::LabelBlue, &["LAlt", "Key4"])
+            .add_keys(InputEvent::LabelPurple, &["LAlt", "Key5"])
+            .add_keys(InputEvent::EditTags, &["LControl", "T"])
+            .add_keys(InputEvent::Bookmark, &["LControl", "B"])
+            .add_key(InputEvent::Compare, "K")
+            .add_key(InputEvent::Flicker, "X")
+            .add_key(InputEvent::Background, "H")
+            .add_key(InputEvent::NearestFilter, "N")
+            .add_key(InputEvent::PixelGrid, "P")
+            .add_key(InputEvent::Zoom100, "Numpad1")
+            .add_key(InputEvent::Zoom200, "Numpad2")
+            .add_key(InputEvent::Zoom400, "Numpad4")
+            .add_key(InputEvent::Zoom800, "Numpad8")
+            .add_key(InputEvent::FitWidth, "W")
+            .add_keys(InputEvent::FitHeight, &["LShift", "W"])
+            .add_key(InputEvent::FillWindow, "L")
+            .add_key(InputEvent::RotateClockwise, "RBracket")
+            .add_key(InputEvent::RotateCounterClockwise, "LBracket")
+            .add_key(InputEvent::MirrorHorizontal, "M")
+            .add_keys(InputEvent::MirrorVertical, &["LShift", "M"])
+            .add_key(InputEvent::Minimap, "O")
+            .add_key(InputEvent::Measure, "D")
             .add_keys(InputEvent::PanRight, &["LShift", "Right"])
             .add_keys(InputEvent::PanLeft, &["LShift", "Left"])
             .add_keys(InputEvent::PanDown, &["LShift", "Down"])
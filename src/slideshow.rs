@@ -0,0 +1,125 @@
+use nalgebra::Vector2;
+use notan::graphics::Texture;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::navigation::{FolderListing, SortOrder};
+use crate::settings::PersistentSettings;
+use crate::utils::PlaybackStatus;
+
+/// How long the cross fade between two slides takes, in seconds
+pub const FADE_DURATION: f32 = 0.6;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlideshowSettings {
+    /// Seconds each image is shown
+    pub interval: f32,
+    /// Start over after the last image
+    pub repeat: bool,
+    pub shuffle: bool,
+    /// Cross fade between images
+    pub fade: bool,
+    /// Switch to fullscreen while the slideshow runs
+    pub fullscreen: bool,
+}
+
+impl Default for SlideshowSettings {
+    fn default() -> Self {
+        Self {
+            interval: 5.0,
+            repeat: true,
+            shuffle: false,
+            fade: true,
+            fullscreen: false,
+        }
+    }
+}
+
+/// The previous slide, drawn on top of the new one while it fades out
+#[derive(Debug)]
+pub struct Fade {
+    pub texture: Texture,
+    pub offset: Vector2<f32>,
+    pub scale: f32,
+    pub start: Instant,
+}
+
+impl Fade {
+    /// Opacity of the previous slide, reaching zero once the fade is over
+    pub fn alpha(&self) -> f32 {
+        1.0 - (self.start.elapsed().as_secs_f32() / FADE_DURATION).min(1.0)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Slideshow {
+    pub active: bool,
+    pub paused: bool,
+    /// When the current slide was shown
+    shown_at: Option<Instant>,
+    /// The slideshow switched to fullscreen and should switch back when stopping
+    pub entered_fullscreen: bool,
+    pub fade: Option<Fade>,
+}
+
+impl Slideshow {
+    pub fn start(&mut self) {
+        self.active = true;
+        self.paused = false;
+        self.shown_at = Some(Instant::now());
+    }
+
+    pub fn stop(&mut self) {
+        self.active = false;
+        self.paused = false;
+        self.fade = None;
+    }
+
+    /// Start timing the slide that is shown now
+    pub fn restart_timer(&mut self) {
+        self.shown_at = Some(Instant::now());
+    }
+
+    /// Whether it's time for the next image. Playing animations need to finish at least once.
+    pub fn is_due(&self, interval: f32, animation: Option<&PlaybackStatus>) -> bool {
+        if !self.active || self.paused {
+            return false;
+        }
+        let elapsed = self
+            .shown_at
+            .map(|t| t.elapsed().as_secs_f32())
+            .unwrap_or_default();
+        // a paused animation would never finish, so it doesn't hold the slideshow up
+        let animation_done = animation.map(|a| a.loops > 0 || a.paused).unwrap_or(true);
+        elapsed >= interval && animation_done
+    }
+}
+
+/// The image to show after `current`, or `None` if the slideshow is over
+pub fn next_slide(
+    current: &Path,
+    listing: &mut FolderListing,
+    settings: &PersistentSettings,
+) -> Option<PathBuf> {
+    let sort = if settings.slideshow.shuffle {
+        SortOrder::Shuffle
+    } else {
+        settings.sort_order
+    };
+    let files = listing.files(
+        current,
+        sort,
+        settings.sort_descending,
+        &settings.file_filter(),
+    );
+    let next = match files.iter().position(|f| f == current) {
+        Some(i) => files.get(i + 1),
+        None => files.first(),
+    };
+    match next {
+        Some(next) => Some(next.clone()),
+        None if settings.slideshow.repeat => files.first().cloned(),
+        None => None,
+    }
+}
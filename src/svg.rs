@@ -0,0 +1,153 @@
+use anyhow::{anyhow, Result};
+use image::RgbaImage;
+use log::{debug, error};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use crate::utils::Frame;
+
+/// Largest edge length a re-rasterised SVG may have
+pub const MAX_SVG_RASTER_SIZE: u32 = 8192;
+
+/// How long zoom requests need to settle before the SVG is rendered again
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+pub fn is_svg(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase() == "svg")
+        .unwrap_or_default()
+}
+
+/// Parse an SVG file
+pub fn load_svg(path: &Path) -> Result<usvg::Tree> {
+    let opt = usvg::Options::default();
+    let svg_data = std::fs::read(path)?;
+    Ok(usvg::Tree::from_data(&svg_data, &opt.to_ref())?)
+}
+
+/// Render a parsed SVG. A zoom of 1.0 renders the document at its original size.
+pub fn render_svg(tree: &usvg::Tree, zoom: f32) -> Result<RgbaImage> {
+    let size = tree.size.to_screen_size();
+    let width = ((size.width() as f32 * zoom).ceil() as u32).max(1);
+    let height = ((size.height() as f32 * zoom).ceil() as u32).max(1);
+
+    let mut pixmap =
+        tiny_skia::Pixmap::new(width, height).ok_or(anyhow!("Can't create SVG pixmap"))?;
+    resvg::render(
+        tree,
+        usvg::FitTo::Zoom(zoom),
+        tiny_skia::Transform::identity(),
+        pixmap.as_mut(),
+    )
+    .ok_or(anyhow!("Can't render SVG"))?;
+
+    RgbaImage::from_raw(width, height, pixmap.data().to_vec())
+        .ok_or(anyhow!("Can't create SVG ImageBuffer with given res"))
+}
+
+/// Keeps a parsed SVG around in a background thread and renders it again
+/// when the effective display resolution changes, so vector art stays crisp.
+#[derive(Debug)]
+pub struct SvgRenderer {
+    pub path: PathBuf,
+    /// Size of the document when rendered at a zoom of 1.0
+    pub base_size: (u32, u32),
+    requested_zoom: f32,
+    /// A render was requested and has not been received yet
+    pub awaiting_frame: bool,
+    zoom_sender: Sender<f32>,
+    /// Errors of renders that did not produce a frame
+    failure_receiver: Receiver<String>,
+}
+
+impl SvgRenderer {
+    pub fn new(path: &Path, base_size: (u32, u32), texture_sender: Sender<Frame>) -> Self {
+        let (zoom_sender, zoom_receiver): (Sender<f32>, Receiver<f32>) = mpsc::channel();
+        let (failure_sender, failure_receiver) = mpsc::channel();
+        let loc = path.to_path_buf();
+
+        thread::spawn(move || {
+            // usvg trees can't be sent between threads, so the tree lives here
+            let tree = match load_svg(&loc) {
+                Ok(tree) => tree,
+                Err(e) => {
+                    error!("{e}");
+                    _ = failure_sender.send(e.to_string());
+                    return;
+                }
+            };
+
+            let mut pending: Option<f32> = None;
+            loop {
+                // Wait for a request. The thread ends once the renderer is dropped.
+                let mut zoom = match pending.take() {
+                    Some(zoom) => zoom,
+                    None => match zoom_receiver.recv() {
+                        Ok(zoom) => zoom,
+                        Err(_) => return,
+                    },
+                };
+
+                // Only use the latest request once zooming has settled
+                loop {
+                    match zoom_receiver.recv_timeout(DEBOUNCE) {
+                        Ok(z) => zoom = z,
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+
+                debug!("Rasterising SVG at {zoom}x");
+                match render_svg(&tree, zoom) {
+                    Ok(img) => {
+                        match zoom_receiver.try_recv() {
+                            // Don't send outdated results if a new image was opened meanwhile
+                            Err(TryRecvError::Disconnected) => return,
+                            // The zoom changed while rendering, render again afterwards
+                            Ok(z) => pending = Some(z),
+                            Err(TryRecvError::Empty) => (),
+                        }
+                        let _ = texture_sender.send(Frame::new_rerender(img, &loc));
+                    }
+                    Err(e) => {
+                        error!("{e}");
+                        _ = failure_sender.send(e.to_string());
+                    }
+                }
+            }
+        });
+
+        Self {
+            path: path.to_path_buf(),
+            base_size,
+            requested_zoom: 1.0,
+            awaiting_frame: false,
+            zoom_sender,
+            failure_receiver,
+        }
+    }
+
+    /// Stop waiting for a render that failed. Returns why it failed.
+    pub fn failure(&mut self) -> Option<String> {
+        let error = self.failure_receiver.try_recv().ok()?;
+        self.awaiting_frame = false;
+        Some(error)
+    }
+
+    /// Request a render at the given zoom. The zoom is clamped so the result is not
+    /// smaller than the original document and not larger than `MAX_SVG_RASTER_SIZE`.
+    pub fn request(&mut self, zoom: f32) {
+        let max_zoom =
+            MAX_SVG_RASTER_SIZE as f32 / self.base_size.0.max(self.base_size.1).max(1) as f32;
+        let zoom = zoom.min(max_zoom).max(1.0);
+
+        // ignore tiny changes to avoid needless renders
+        if (zoom / self.requested_zoom - 1.0).abs() > 0.02 {
+            self.requested_zoom = zoom;
+            // the thread is gone if the SVG could not be loaded
+            self.awaiting_frame = self.zoom_sender.send(zoom).is_ok();
+        }
+    }
+}
@@ -3,6 +3,7 @@ use cmd_lib::run_cmd;
 use crate::image_editing::{process_pixels, ImageOperation, ScaleFilter};
 
 use super::*;
+use image::RgbaImage;
 use std::{path::PathBuf, time::Instant};
 
 #[test]
@@ -164,3 +165,691 @@ fn bench_process_all() {
     }
     info!("{} ms mean", total / iters);
 }
+
+#[test]
+fn load_ktx_qoi() {
+    let ktx = open_image(&PathBuf::from("tests/test.ktx2")).unwrap();
+    assert_eq!(ktx.frames[0].buffer.dimensions(), (16, 8));
+    assert_eq!(
+        crate::ktx::level_count(&PathBuf::from("tests/test.ktx2")),
+        2
+    );
+    let mip = crate::ktx::open_ktx(&PathBuf::from("tests/test.ktx2"), 1).unwrap();
+    assert_eq!(mip.dimensions(), (8, 4));
+
+    let qoi = open_image(&PathBuf::from("tests/test.qoi")).unwrap();
+    assert_eq!(qoi.frames[0].buffer.dimensions(), (16, 8));
+}
+
+#[test]
+fn ktx_too_many_levels() {
+    // a 1x1 KTX 1.1 rgba texture claiming 40 empty mip levels
+    let mut data = vec![
+        0xAB, 0x4B, 0x54, 0x58, 0x20, 0x31, 0x31, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+    ];
+    for value in [
+        0x04030201, 0x1401, 1, 0x1908, 0x1908, 0x1908, 1, 1, 0, 0, 1, 40, 0,
+    ] {
+        data.extend(u32::to_le_bytes(value));
+    }
+    data.extend([0; 40 * 4]);
+    let path = std::env::temp_dir().join(format!("oculante_levels_{}.ktx", std::process::id()));
+    std::fs::write(&path, data).unwrap();
+
+    assert_eq!(crate::ktx::level_count(&path), 32);
+    assert!(crate::ktx::open_ktx(&path, 35).is_err());
+    _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn settings_gain_new_shortcuts() {
+    use crate::shortcuts::{InputEvent, ShortcutExt, Shortcuts};
+
+    // settings as saved by an older version, with a rebound key
+    let old = r#"{
+        "accent_color": [255, 0, 75],
+        "vsync": true,
+        "shortcuts": {"Quit": ["Q"], "Fullscreen": ["X"]}
+    }"#;
+    let settings = crate::settings::PersistentSettings::from_reader(old.as_bytes()).unwrap();
+    let defaults = Shortcuts::default_keys();
+    assert_eq!(settings.shortcuts.len(), defaults.len());
+    assert!(settings.shortcuts[&InputEvent::Fullscreen].contains("X"));
+    assert_eq!(
+        settings.shortcuts[&InputEvent::Measure],
+        defaults[&InputEvent::Measure]
+    );
+}
+
+#[test]
+fn export_animation_roundtrip() {
+    let frames: Vec<Frame> = (0..3)
+        .map(|i| {
+            let mut frame = Frame::new_still(RgbaImage::from_pixel(
+                8,
+                4,
+                image::Rgba([i * 80, 0, 0, 255]),
+            ));
+            frame.delay = 50;
+            frame
+        })
+        .collect();
+
+    let dir = std::env::temp_dir().join(format!("oculante_export_{}", std::process::id()));
+    _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for format in crate::export::ANIMATION_FORMATS {
+        let target = dir.join(format!("export.{format}"));
+        crate::export::export_animation(&frames, &target, format).unwrap();
+        assert!(target.exists());
+    }
+
+    let gif = open_image(&dir.join("export.gif")).unwrap();
+    assert_eq!(gif.frames.len(), 3);
+    assert_eq!(gif.frames[1].delay, 50);
+
+    let files = crate::export::export_sequence(&frames, &dir.join("frames"), "frame").unwrap();
+    assert_eq!(files.len(), 3);
+    _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn export_detected_sequence() {
+    use crate::export::{animation_frames, export_animation};
+
+    let dir = std::env::temp_dir().join(format!("oculante_export_sequence_{}", std::process::id()));
+    _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for i in 1..=4 {
+        RgbaImage::from_pixel(4, 4, image::Rgba([i * 50, 0, 0, 255]))
+            .save(dir.join(format!("shot_{i:04}.png")))
+            .unwrap();
+    }
+    let current = dir.join("shot_0002.png");
+    let sequence = crate::sequence::detect_sequence(&current).unwrap();
+
+    // the whole sequence is exported, not just the current file
+    let frames = animation_frames(&current, Some(&sequence), 25.0).unwrap();
+    assert_eq!(frames.len(), 4);
+    assert_eq!(frames[0].delay, 40);
+    assert_eq!(frames[3].buffer.get_pixel(0, 0)[0], 200);
+    assert_eq!(animation_frames(&current, None, 25.0).unwrap().len(), 1);
+
+    let target = dir.join("export.gif");
+    export_animation(&frames, &target, "gif").unwrap();
+    let gif = animation_frames(&target, None, 25.0).unwrap();
+    assert_eq!(gif.len(), 4);
+    assert_eq!(gif[1].delay, 40);
+    _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn frame_store_eviction() {
+    let frame = || Frame::new_still(RgbaImage::new(4, 4));
+    // room for two frames
+    let mut store = FrameStore::new(2 * 4 * 4 * 4);
+    store.insert(0, frame());
+    store.insert(1, frame());
+    // replacing a frame makes it the newest
+    store.insert(0, frame());
+    store.insert(2, frame());
+    assert!(store.get(0).is_some() && store.get(2).is_some());
+    assert!(store.get(1).is_none());
+}
+
+#[test]
+fn detect_image_sequence() {
+    let dir = std::env::temp_dir().join(format!("oculante_sequence_{}", std::process::id()));
+    _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for i in [1, 2, 3, 5, 8, 9] {
+        RgbaImage::new(4, 4)
+            .save(dir.join(format!("shot_{i:04}.png")))
+            .unwrap();
+    }
+    // not part of the sequence
+    RgbaImage::new(4, 4).save(dir.join("shot_7.png")).unwrap();
+    RgbaImage::new(4, 4)
+        .save(dir.join("other_0004.png"))
+        .unwrap();
+
+    let sequence = crate::sequence::detect_sequence(&dir.join("shot_0002.png")).unwrap();
+    assert_eq!(sequence.files.len(), 6);
+    assert_eq!(sequence.missing, vec![4, 6, 7]);
+    assert_eq!(sequence.missing_summary(), "4, 6-7");
+
+    let mut cache =
+        crate::sequence::FrameCache::new(sequence.paths(), 40, std::sync::mpsc::channel().0);
+    assert_eq!(cache.frame(5).unwrap().buffer.dimensions(), (4, 4));
+    assert!(cache.frame(6).is_none());
+    _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn folder_sorting() {
+    use crate::navigation::{list_folder, natural_cmp, FileFilter, SortOrder};
+    use std::cmp::Ordering;
+
+    assert_eq!(natural_cmp("img_2.png", "img_10.png"), Ordering::Less);
+    assert_eq!(natural_cmp("img_010.png", "img_9.png"), Ordering::Greater);
+    assert_eq!(natural_cmp("B.png", "a.png"), Ordering::Greater);
+
+    let dir = std::env::temp_dir().join(format!("oculante_sorting_{}", std::process::id()));
+    _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for (name, size) in [("img_10.png", 1), ("img_2.png", 3), ("cat_1.png", 2)] {
+        RgbaImage::new(size, size).save(dir.join(name)).unwrap();
+    }
+    std::fs::write(dir.join("notes.txt"), "not an image").unwrap();
+    // the image crate can't read its size
+    std::fs::write(
+        dir.join("logo.svg"),
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="5" height="5"/>"#,
+    )
+    .unwrap();
+
+    let names = |files: Vec<PathBuf>| {
+        files
+            .iter()
+            .map(|f| f.file_name().unwrap().to_string_lossy().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        names(list_folder(
+            &dir,
+            SortOrder::Name,
+            false,
+            &FileFilter::default(),
+            0
+        )),
+        vec!["cat_1.png", "img_2.png", "img_10.png", "logo.svg"]
+    );
+    assert_eq!(
+        names(list_folder(
+            &dir,
+            SortOrder::Dimensions,
+            true,
+            &FileFilter::default(),
+            0
+        )),
+        vec!["logo.svg", "img_2.png", "cat_1.png", "img_10.png"]
+    );
+    assert_eq!(
+        names(list_folder(
+            &dir,
+            SortOrder::Name,
+            false,
+            &FileFilter {
+                name: "IMG".into(),
+                ..Default::default()
+            },
+            0
+        )),
+        vec!["img_2.png", "img_10.png"]
+    );
+    assert_eq!(
+        list_folder(&dir, SortOrder::Shuffle, false, &FileFilter::default(), 7).len(),
+        4
+    );
+    _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn thumbnail_cache_names() {
+    use crate::thumbnails::{cache_file_name, escape_path};
+
+    // example from the freedesktop.org thumbnail spec
+    let uri = format!("file://{}", escape_path(b"/home/jens/photos/me.png"));
+    assert_eq!(uri, "file:///home/jens/photos/me.png");
+    assert_eq!(
+        cache_file_name(&uri),
+        "c6ee772d9e49320e97ec29a7eb5b1697.png"
+    );
+    assert_eq!(escape_path("/a b/ü.png".as_bytes()), "/a%20b/%C3%BC.png");
+}
+
+#[test]
+fn file_actions_undo() {
+    use crate::file_actions::{copy_to, move_to, rename, undo};
+
+    let dir = std::env::temp_dir().join(format!("oculante_file_actions_{}", std::process::id()));
+    _ = std::fs::remove_dir_all(&dir);
+    let target = dir.join("keep");
+    std::fs::create_dir_all(&target).unwrap();
+    let image = dir.join("a.png");
+    RgbaImage::new(1, 1).save(&image).unwrap();
+    let sidecar = dir.join("a.png.xmp");
+    std::fs::write(&sidecar, "").unwrap();
+
+    let moved = move_to(&image, &target).unwrap();
+    assert!(!image.exists() && target.join("a.png").exists());
+    assert!(!sidecar.exists() && target.join("a.png.xmp").exists());
+    assert_eq!(undo(&moved).unwrap(), image);
+    assert!(image.exists() && !target.join("a.png").exists());
+    assert!(sidecar.exists() && !target.join("a.png.xmp").exists());
+
+    let renamed = rename(&image, "b.png").unwrap();
+    assert!(dir.join("b.png").exists() && dir.join("b.png.xmp").exists());
+    undo(&renamed).unwrap();
+    assert!(image.exists() && sidecar.exists());
+
+    copy_to(&image, &target).unwrap();
+    // existing files are never overwritten
+    assert!(copy_to(&image, &target).is_err());
+    assert!(rename(&image, "../b.png").is_err());
+    _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn xmp_sidecars() {
+    use crate::navigation::{list_folder, FileFilter, SortOrder};
+    use crate::xmp::{parse, read, update, write, ColorLabel, XmpData};
+
+    // darktable writes properties as attributes and keeps its own data around
+    let darktable = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+    xmlns:darktable="http://darktable.sf.net/"
+   xmp:Rating="3"
+   darktable:history_end="2">
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>"#;
+    assert_eq!(parse(darktable).rating, 3);
+
+    let data = XmpData {
+        rating: 5,
+        label: Some(ColorLabel::Green),
+        tags: vec!["cats & dogs".into(), "holiday".into()],
+    };
+    let updated = update(darktable, &data).unwrap();
+    assert!(updated.contains(r#"darktable:history_end="2""#));
+    assert_eq!(parse(&updated), data);
+    // updating again replaces the old values
+    assert_eq!(
+        parse(&update(&updated, &XmpData::default()).unwrap()),
+        XmpData::default()
+    );
+    // rejected images stay rejected when tagging them
+    let rejected = darktable.replace(r#"xmp:Rating="3""#, r#"xmp:Rating="-1""#);
+    let mut data = parse(&rejected);
+    assert_eq!(data.rating, -1);
+    data.tags.push("blurry".into());
+    assert_eq!(parse(&update(&rejected, &data).unwrap()), data);
+
+    let dir = std::env::temp_dir().join(format!("oculante_xmp_{}", std::process::id()));
+    _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for name in ["a.png", "b.png"] {
+        RgbaImage::new(1, 1).save(dir.join(name)).unwrap();
+    }
+    write(&dir.join("a.png"), &data).unwrap();
+    assert!(dir.join("a.png.xmp").exists());
+    assert_eq!(read(&dir.join("a.png")), data);
+    assert_eq!(read(&dir.join("b.png")), XmpData::default());
+
+    let filter = FileFilter {
+        min_rating: 4,
+        ..Default::default()
+    };
+    assert_eq!(
+        list_folder(&dir, SortOrder::Name, false, &filter, 0),
+        vec![dir.join("a.png")]
+    );
+    let filter = FileFilter {
+        tag: "Holiday".into(),
+        ..Default::default()
+    };
+    assert_eq!(
+        list_folder(&dir, SortOrder::Name, false, &filter, 0).len(),
+        1
+    );
+    _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn recent_files() {
+    use crate::session::{push_recent, push_recent_file, RECENT_LEN};
+    use std::path::Path;
+
+    let mut recent = vec![];
+    push_recent_file(&mut recent, Path::new("/a/1.png"));
+    push_recent_file(&mut recent, Path::new("/b/1.png"));
+    // browsing a folder only keeps the last image of it
+    push_recent_file(&mut recent, Path::new("/b/2.png"));
+    assert_eq!(
+        recent,
+        vec![PathBuf::from("/b/2.png"), PathBuf::from("/a/1.png")]
+    );
+    push_recent_file(&mut recent, Path::new("/a/1.png"));
+    assert_eq!(recent[0], PathBuf::from("/a/1.png"));
+    assert_eq!(recent.len(), 2);
+
+    let mut dirs = vec![];
+    for i in 0..RECENT_LEN + 5 {
+        push_recent(&mut dirs, &PathBuf::from(format!("/dir{i}")));
+    }
+    assert_eq!(dirs.len(), RECENT_LEN);
+    assert_eq!(dirs[0], PathBuf::from(format!("/dir{}", RECENT_LEN + 4)));
+}
+
+#[test]
+fn compare_alignment() {
+    use crate::compare::{Anchor, Compare, CompareMode};
+    use nalgebra::Vector2;
+
+    let first = Vector2::new(10.0, 10.0);
+    let mut compare = Compare {
+        image: Some(RgbaImage::new(20, 10)),
+        anchor: Anchor::Center,
+        ..Default::default()
+    };
+    assert_eq!(compare.shift(first), Vector2::new(5.0, 0.0));
+    // over the first image, the second one is probed at the matching position
+    assert_eq!(
+        compare.probe(Vector2::new(2.0, 3.0), first),
+        (Vector2::new(2.0, 3.0), Vector2::new(7.0, 3.0))
+    );
+    // and the other way around
+    assert_eq!(
+        compare.probe(Vector2::new(20.0, 3.0), first),
+        (Vector2::new(-3.0, 3.0), Vector2::new(2.0, 3.0))
+    );
+    assert_eq!(
+        compare.bounds(first),
+        (Vector2::zeros(), Vector2::new(38.0, 10.0))
+    );
+
+    compare.mode = CompareMode::Stacked;
+    assert_eq!(compare.origin(first), Vector2::new(-5.0, 18.0));
+    assert_eq!(
+        compare.bounds(first),
+        (Vector2::new(-5.0, 0.0), Vector2::new(20.0, 28.0))
+    );
+}
+
+#[test]
+fn image_difference() {
+    use crate::diff::{changed_pixels, difference};
+    use image::Rgba;
+
+    let a = RgbaImage::from_pixel(4, 4, Rgba([100, 100, 100, 255]));
+    let mut b = a.clone();
+    b.put_pixel(1, 2, Rgba([110, 100, 90, 255]));
+
+    let diff = difference(&a, &b, (0, 0), 2.0);
+    assert_eq!(diff.get_pixel(1, 2), &Rgba([20, 0, 20, 255]));
+    assert_eq!(diff.get_pixel(0, 0), &Rgba([0, 0, 0, 255]));
+
+    let (mask, count) = changed_pixels(&a, &b, (0, 0), 0);
+    assert_eq!(count, 1);
+    assert_eq!(mask.get_pixel(0, 0)[3], 0);
+    assert_eq!(changed_pixels(&a, &b, (0, 0), 10).1, 0);
+
+    // pixels without a counterpart in the smaller image count as changed
+    let small = RgbaImage::from_pixel(2, 4, Rgba([100, 100, 100, 255]));
+    assert_eq!(changed_pixels(&a, &small, (0, 0), 0).1, 8);
+    assert_eq!(changed_pixels(&a, &small, (-2, 0), 0).1, 8);
+}
+
+#[test]
+fn image_metrics() {
+    use crate::diff::{metrics, Metric};
+    use image::Rgba;
+
+    let a = RgbaImage::from_fn(32, 32, |x, y| Rgba([(x * 8) as u8, (y * 8) as u8, 50, 255]));
+    let same = metrics(&a, &a).unwrap();
+    assert_eq!(same.combined_mse, 0.0);
+    assert!(same.combined_psnr.is_infinite());
+    assert!((same.combined_ssim - 1.0).abs() < 1e-9);
+
+    let mut b = a.clone();
+    b.put_pixel(3, 3, Rgba([0, 0, 0, 255]));
+    let changed = metrics(&a, &b).unwrap();
+    assert!(changed.combined_ssim < 1.0);
+    assert_eq!(changed.max_error, [24, 24, 50, 0]);
+    assert!(changed.within(Metric::Psnr, 40.0));
+    assert!(!changed.within(Metric::MaxError, 10.0));
+    assert_eq!("max-error".parse::<Metric>().unwrap(), Metric::MaxError);
+
+    assert!(metrics(&a, &RgbaImage::new(2, 2)).is_err());
+}
+
+#[test]
+fn checkerboard_tile() {
+    use crate::background::{checker_tile, BackgroundMode, BackgroundSettings};
+
+    let tile = checker_tile(4, [[200, 200, 200], [100, 100, 100]]);
+    assert_eq!(tile.dimensions(), (8, 8));
+    assert_eq!(tile.get_pixel(3, 3).0, [200, 200, 200, 255]);
+    assert_eq!(tile.get_pixel(4, 3).0, [100, 100, 100, 255]);
+    assert_eq!(tile.get_pixel(3, 4).0, [100, 100, 100, 255]);
+    assert_eq!(tile.get_pixel(7, 7).0, [200, 200, 200, 255]);
+
+    let mut settings = BackgroundSettings::default();
+    settings.toggle();
+    assert_eq!(settings.mode, BackgroundMode::Checkerboard);
+    settings.toggle();
+    assert_eq!(settings.mode, BackgroundMode::Solid);
+}
+
+#[test]
+fn pixel_grid_visible() {
+    use crate::pixel_grid::visible_pixels;
+    use nalgebra::Vector2;
+
+    let window = Vector2::new(100.0, 50.0);
+    // the whole image fits
+    assert_eq!(
+        visible_pixels(Vector2::new(10.0, 10.0), 2.0, (20, 10), window),
+        (0..20, 0..10)
+    );
+    // zoomed in, panned to the right and down: only a part is on screen
+    assert_eq!(
+        visible_pixels(Vector2::new(-45.0, -100.0), 10.0, (64, 64), window),
+        (4..15, 10..15)
+    );
+    // panned out of view
+    let (columns, _) = visible_pixels(Vector2::new(200.0, 0.0), 1.0, (20, 10), window);
+    assert!(columns.is_empty());
+}
+
+#[test]
+fn pixel_values_reachable() {
+    use crate::pixel_grid::{PixelGridSettings, VALUES_ZOOM};
+    use crate::zoom::MAX_SCALE;
+
+    // the values must show up before zooming stops
+    assert!(VALUES_ZOOM <= MAX_SCALE);
+    assert!(PixelGridSettings::default().grid_zoom <= MAX_SCALE);
+}
+
+#[test]
+fn fit_modes() {
+    use crate::zoom::{FitMode, MAX_SCALE, MIN_SCALE};
+    use nalgebra::Vector2;
+
+    let window = Vector2::new(800.0, 600.0);
+    let wide = Vector2::new(1600.0, 400.0);
+    assert_eq!(FitMode::Shrink.scale(wide, window), 0.5);
+    assert_eq!(FitMode::Width.scale(wide, window), 0.5);
+    assert_eq!(FitMode::Height.scale(wide, window), 1.5);
+    assert_eq!(FitMode::Fill.scale(wide, window), 1.5);
+    assert_eq!(FitMode::Actual.scale(wide, window), 1.0);
+
+    // small images are only enlarged when asked for
+    let icon = Vector2::new(16.0, 16.0);
+    assert_eq!(FitMode::Shrink.scale(icon, window), 1.0);
+    assert_eq!(FitMode::Fit.scale(icon, window), 37.5);
+    assert_eq!(
+        FitMode::Fill.scale(Vector2::new(1.0, 1.0), window),
+        MAX_SCALE
+    );
+
+    // huge images fit even below the smallest zoom
+    let huge = Vector2::new(40000.0, 1000.0);
+    assert_eq!(FitMode::Fit.scale(huge, window), 0.02);
+    assert!(FitMode::Fit.scale(huge, window) < MIN_SCALE);
+}
+
+#[test]
+fn view_orientation() {
+    use crate::orientation::Orientation;
+    use nalgebra::Vector2;
+
+    let size = Vector2::new(4.0, 2.0);
+    let mut orientation = Orientation::default();
+    orientation.rotate(true);
+    assert_eq!(orientation.view_size(size), Vector2::new(2.0, 4.0));
+    // the top left pixel ends up top right
+    assert_eq!(
+        orientation.to_view(Vector2::new(0.5, 0.5), size),
+        Vector2::new(1.5, 0.5)
+    );
+    orientation.mirror_horizontal = true;
+    assert_eq!(
+        orientation.to_view(Vector2::new(0.5, 0.5), size),
+        Vector2::new(0.5, 0.5)
+    );
+
+    // the probe finds the source pixel in every orientation
+    for rotation in 0..4 {
+        for (mirror_horizontal, mirror_vertical) in
+            [(false, false), (true, false), (false, true), (true, true)]
+        {
+            let orientation = Orientation {
+                rotation,
+                mirror_horizontal,
+                mirror_vertical,
+            };
+            let p = Vector2::new(2.5, 0.5);
+            assert_eq!(orientation.to_image(orientation.to_view(p, size), size), p);
+            let m = orientation.matrix(size, Vector2::new(10.0, 20.0), 2.0);
+            let screen = m.transform_point2(notan::math::Vec2::new(p.x, p.y));
+            let expected = Vector2::new(10.0, 20.0) + orientation.to_view(p, size) * 2.0;
+            assert_eq!(Vector2::new(screen.x, screen.y), expected);
+        }
+    }
+}
+
+#[test]
+fn minimap_navigation() {
+    use crate::minimap::{center_on, is_needed, viewport};
+    use nalgebra::Vector2;
+
+    let window = Vector2::new(800.0, 600.0);
+    let image = Vector2::new(4000.0, 3000.0);
+    assert!(!is_needed(Vector2::new(0.0, 0.0), 0.2, image, window));
+    assert!(is_needed(Vector2::new(0.0, 0.0), 1.0, image, window));
+
+    // zoomed to 200% and panned into the image
+    let offset = Vector2::new(-1000.0, -400.0);
+    assert_eq!(
+        viewport(offset, 2.0, window),
+        (Vector2::new(500.0, 200.0), Vector2::new(400.0, 300.0))
+    );
+    // centering on a pixel puts the viewport around it
+    let offset = center_on(Vector2::new(2000.0, 1500.0), 2.0, window);
+    let (min, size) = viewport(offset, 2.0, window);
+    assert_eq!(min + size / 2.0, Vector2::new(2000.0, 1500.0));
+}
+
+#[test]
+fn measurements() {
+    use crate::measure::{physical, snap, Measure, MeasureMode};
+    use nalgebra::Vector2;
+
+    assert_eq!(snap(Vector2::new(3.9, 7.1)), Vector2::new(3.5, 7.5));
+
+    let mut measure = Measure {
+        mode: MeasureMode::Line,
+        ..Default::default()
+    };
+    assert!(measure.rows().is_empty());
+    measure.begin(Vector2::new(0.2, 0.7));
+    measure.drag(Vector2::new(3.6, 4.1));
+    let text = measure.text();
+    assert!(text.contains("Length: 5.00 px"), "{text}");
+    assert!(text.contains("Angle: -53.13°"), "{text}");
+    assert!(text.contains("dx, dy: 3, 4"), "{text}");
+    assert!(!text.contains("Physical"));
+
+    measure.mode = MeasureMode::Rectangle;
+    measure.dpi = Some(254.0);
+    let text = measure.text();
+    assert!(text.contains("Size: 4x5 px"), "{text}");
+    assert!(text.contains("Area: 20 px²"), "{text}");
+    assert!(text.contains("Aspect: 0.8000"), "{text}");
+    assert!(
+        text.contains("Physical: 0.40 mm / 0.016 in x 0.50 mm / 0.020 in"),
+        "{text}"
+    );
+
+    assert_eq!(physical(300.0, 300.0), "25.40 mm / 1.000 in");
+}
+
+#[test]
+fn idle_watching() {
+    use crate::watch::{idle_wait, IDLE_POLL};
+    use std::time::{Duration, Instant};
+
+    // right after input the window runs at full speed
+    assert_eq!(idle_wait(Instant::now()), Duration::ZERO);
+    // without input it keeps looking for changes slowly
+    let idle = Instant::now() - Duration::from_secs(2);
+    assert_eq!(idle_wait(idle), IDLE_POLL);
+}
+
+#[test]
+fn watch_folder_idle() {
+    use crate::watch::{WatchFolder, IDLE_POLL};
+
+    let dir = std::env::temp_dir().join(format!("oculante_watch_{}", std::process::id()));
+    _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut watch_folder = WatchFolder::default();
+    assert_eq!(watch_folder.start(&dir), None);
+
+    // an idle window polls once per tick, without any input in between
+    let image = dir.join("new.png");
+    RgbaImage::new(4, 4).save(&image).unwrap();
+    let mut newest = None;
+    for _ in 0..50 {
+        std::thread::sleep(IDLE_POLL);
+        newest = watch_folder.poll();
+        if newest.is_some() {
+            break;
+        }
+    }
+    // notify may report the folder with symlinks resolved
+    assert_eq!(
+        newest.as_deref().and_then(|p| p.file_name()),
+        image.file_name()
+    );
+
+    watch_folder.stop();
+    _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn slideshow_waits_for_animations() {
+    use crate::slideshow::Slideshow;
+    use crate::utils::PlaybackStatus;
+
+    let mut slideshow = Slideshow::default();
+    slideshow.start();
+    assert!(slideshow.is_due(0.0, None));
+
+    // however long it takes, an animation plays through once
+    let mut playing = PlaybackStatus::default();
+    assert!(!slideshow.is_due(0.0, Some(&playing)));
+    playing.loops = 1;
+    assert!(slideshow.is_due(0.0, Some(&playing)));
+
+    let paused = PlaybackStatus {
+        paused: true,
+        ..Default::default()
+    };
+    assert!(slideshow.is_due(0.0, Some(&paused)));
+}
@@ -0,0 +1,533 @@
+use anyhow::{anyhow, bail, Result};
+use image::{DynamicImage, RgbaImage};
+use log::{debug, error, info};
+use notan::egui::{EguiRegisterTexture, TextureId};
+use notan::graphics::Texture;
+use notan::prelude::Graphics;
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::UNIX_EPOCH;
+
+use crate::navigation::{list_folder, SortOrder};
+use crate::utils::{open_image, ImageExt};
+
+/// Longest edge of a thumbnail
+pub const THUMB_SIZE: u32 = 160;
+
+/// Number of threads generating thumbnails
+const WORKERS: usize = 4;
+
+/// Thumbnails kept as textures. The ones shown longest ago are dropped first.
+const MAX_TEXTURES: usize = 400;
+
+/// Where failed thumbnails are recorded, so broken files are not decoded again
+const FAIL_DIR: &str = concat!("oculante-", env!("CARGO_PKG_VERSION"));
+
+/// What is shown below a thumbnail
+#[derive(Debug, Clone, Copy)]
+pub struct ThumbnailInfo {
+    /// Size of the full image, if known
+    pub dimensions: Option<(u32, u32)>,
+    pub file_size: u64,
+}
+
+#[derive(Debug)]
+pub enum Thumbnail {
+    Loading,
+    Ready(Texture, ThumbnailInfo),
+    Failed,
+}
+
+type ThumbnailResult = (PathBuf, Result<(RgbaImage, ThumbnailInfo)>);
+
+/// Generates thumbnails in background threads and keeps them as textures
+#[derive(Debug)]
+pub struct Thumbnails {
+    thumbnails: HashMap<PathBuf, Thumbnail>,
+    /// When each thumbnail was last asked for, counted in requests
+    last_used: HashMap<PathBuf, u64>,
+    requests: u64,
+    /// Textures of dropped thumbnails, to be unregistered from egui
+    released: Vec<Texture>,
+    request_sender: Sender<PathBuf>,
+    result_receiver: Receiver<ThumbnailResult>,
+}
+
+impl Default for Thumbnails {
+    fn default() -> Self {
+        let (request_sender, request_receiver) = mpsc::channel::<PathBuf>();
+        let (result_sender, result_receiver) = mpsc::channel::<ThumbnailResult>();
+        let request_receiver = Arc::new(Mutex::new(request_receiver));
+
+        for _ in 0..WORKERS {
+            let request_receiver = request_receiver.clone();
+            let result_sender = result_sender.clone();
+            thread::spawn(move || loop {
+                // the lock is only held while waiting for the next request
+                let request = match request_receiver.lock() {
+                    Ok(receiver) => receiver.recv(),
+                    Err(_) => return,
+                };
+                match request {
+                    Ok(path) => {
+                        let thumbnail = make_thumbnail(&path);
+                        if result_sender.send((path, thumbnail)).is_err() {
+                            return;
+                        }
+                    }
+                    Err(_) => return,
+                }
+            });
+        }
+
+        Self {
+            thumbnails: Default::default(),
+            last_used: Default::default(),
+            requests: 0,
+            released: vec![],
+            request_sender,
+            result_receiver,
+        }
+    }
+}
+
+impl Thumbnails {
+    /// The thumbnail of `path`. Generating it starts on the first request.
+    pub fn get(&mut self, path: &Path) -> &Thumbnail {
+        if !self.thumbnails.contains_key(path) {
+            _ = self.request_sender.send(path.to_path_buf());
+        }
+        self.requests += 1;
+        self.last_used.insert(path.to_path_buf(), self.requests);
+        self.thumbnails
+            .entry(path.to_path_buf())
+            .or_insert(Thumbnail::Loading)
+    }
+
+    /// Turn finished thumbnails into textures
+    pub fn receive(&mut self, gfx: &mut Graphics) {
+        self.release_textures(gfx);
+        while let Ok((path, result)) = self.result_receiver.try_recv() {
+            let thumbnail = match result {
+                Ok((img, info)) => match img.to_texture(gfx) {
+                    Some(texture) => Thumbnail::Ready(texture, info),
+                    None => Thumbnail::Failed,
+                },
+                Err(e) => {
+                    debug!("No thumbnail for {}: {e}", path.display());
+                    Thumbnail::Failed
+                }
+            };
+            self.drop_thumbnail(&path);
+            self.thumbnails.insert(path, thumbnail);
+        }
+        self.limit();
+    }
+
+    /// Unregister the textures of dropped thumbnails from egui
+    pub fn release_textures(&mut self, gfx: &mut Graphics) {
+        for texture in self.released.drain(..) {
+            gfx.egui_remove_texture(TextureId::User(texture.id()));
+        }
+    }
+
+    /// Drop the least recently shown textures beyond `MAX_TEXTURES`
+    fn limit(&mut self) {
+        let mut ready = self
+            .thumbnails
+            .iter()
+            .filter(|(_, t)| matches!(t, Thumbnail::Ready(..)))
+            .map(|(path, _)| (self.last_used.get(path).copied().unwrap_or_default(), path))
+            .collect::<Vec<_>>();
+        if ready.len() <= MAX_TEXTURES {
+            return;
+        }
+        ready.sort_unstable();
+        let stale = ready[..ready.len() - MAX_TEXTURES]
+            .iter()
+            .map(|(_, path)| path.to_path_buf())
+            .collect::<Vec<_>>();
+        for path in stale {
+            self.drop_thumbnail(&path);
+        }
+    }
+
+    fn drop_thumbnail(&mut self, path: &Path) {
+        if let Some(Thumbnail::Ready(texture, _)) = self.thumbnails.remove(path) {
+            self.released.push(texture);
+        }
+        self.last_used.remove(path);
+    }
+
+    /// Whether thumbnails are still being generated
+    pub fn is_loading(&self) -> bool {
+        self.thumbnails
+            .values()
+            .any(|t| matches!(t, Thumbnail::Loading))
+    }
+
+    /// Generate the thumbnail of a changed file again
+    pub fn remove(&mut self, path: &Path) {
+        self.drop_thumbnail(path);
+    }
+
+    /// Drop all thumbnails, for example when changing folders
+    pub fn clear(&mut self) {
+        for (_, thumbnail) in self.thumbnails.drain() {
+            if let Thumbnail::Ready(texture, _) = thumbnail {
+                self.released.push(texture);
+            }
+        }
+        self.last_used.clear();
+    }
+}
+
+/// The thumbnail browser for the current folder
+#[derive(Debug, Default)]
+pub struct ThumbnailGrid {
+    pub enabled: bool,
+    /// Index of the highlighted file
+    pub selected: usize,
+    /// Thumbnails per row in the last frame
+    pub columns: usize,
+    /// Rows visible in the last frame
+    pub visible_rows: Range<usize>,
+    /// Scroll the selection into view in the next frame
+    pub scroll_to_selected: bool,
+    /// The folder the thumbnails belong to
+    pub folder: Option<PathBuf>,
+    pub thumbnails: Thumbnails,
+}
+
+impl ThumbnailGrid {
+    /// Move the selection by `delta` files, staying within `count` files
+    pub fn move_selection(&mut self, delta: isize, count: usize) {
+        if count == 0 {
+            return;
+        }
+        self.selected = (self.selected as isize + delta).clamp(0, count as isize - 1) as usize;
+        self.scroll_to_selected = true;
+    }
+
+    /// Move the selection by whole rows
+    pub fn move_rows(&mut self, rows: isize, count: usize) {
+        self.move_selection(rows * self.columns.max(1) as isize, count);
+    }
+}
+
+/// A thumbnail for the grid, taken from the shared thumbnail cache if possible
+pub fn make_thumbnail(path: &Path) -> Result<(RgbaImage, ThumbnailInfo)> {
+    let (thumbnail, info) = cache_thumbnail(path, CacheSize::Large)?;
+    Ok((fit(&DynamicImage::ImageRgba8(thumbnail), THUMB_SIZE), info))
+}
+
+/// Scale an image down so its longest edge is at most `size`
+fn fit(img: &DynamicImage, size: u32) -> RgbaImage {
+    if img.width() <= size && img.height() <= size {
+        img.to_rgba8()
+    } else {
+        img.thumbnail(size, size).into_rgba8()
+    }
+}
+
+/// Thumbnail sizes of the freedesktop.org Thumbnail Managing Standard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheSize {
+    Normal,
+    Large,
+}
+
+impl CacheSize {
+    /// Longest edge in pixels
+    pub fn pixels(&self) -> u32 {
+        match self {
+            CacheSize::Normal => 128,
+            CacheSize::Large => 256,
+        }
+    }
+
+    fn dir_name(&self) -> &'static str {
+        match self {
+            CacheSize::Normal => "normal",
+            CacheSize::Large => "large",
+        }
+    }
+}
+
+/// The thumbnail cache shared with other applications, `~/.cache/thumbnails` on Linux
+fn cache_root() -> Result<PathBuf> {
+    Ok(dirs::cache_dir()
+        .ok_or(anyhow!("Can't get cache dir"))?
+        .join("thumbnails"))
+}
+
+/// Percent-encode a path for use in a file uri
+pub fn escape_path(path: &[u8]) -> String {
+    path.iter()
+        .map(|&b| {
+            if b.is_ascii_alphanumeric() || b"-_.~!$&'()*+,/:=@".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{b:02X}")
+            }
+        })
+        .collect()
+}
+
+/// The `file://` uri of `path`, which identifies it in the cache
+pub fn file_uri(path: &Path) -> Result<String> {
+    let path = path.canonicalize()?;
+    #[cfg(unix)]
+    let escaped = {
+        use std::os::unix::ffi::OsStrExt;
+        escape_path(path.as_os_str().as_bytes())
+    };
+    #[cfg(not(unix))]
+    let escaped = {
+        let path = path.to_string_lossy().replace('\\', "/");
+        format!(
+            "/{}",
+            escape_path(path.trim_start_matches("//?/").as_bytes())
+        )
+    };
+    Ok(format!("file://{escaped}"))
+}
+
+/// Cache file name for a file uri: its md5 hash
+pub fn cache_file_name(uri: &str) -> String {
+    format!("{:x}.png", md5::compute(uri))
+}
+
+fn modified_secs(path: &Path) -> Result<u64> {
+    Ok(std::fs::metadata(path)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)?
+        .as_secs())
+}
+
+/// Text chunks of a cached thumbnail
+fn read_metadata(cached: &Path) -> Result<HashMap<String, String>> {
+    let reader = png::Decoder::new(BufReader::new(File::open(cached)?)).read_info()?;
+    Ok(reader
+        .info()
+        .uncompressed_latin1_text
+        .iter()
+        .map(|chunk| (chunk.keyword.clone(), chunk.text.clone()))
+        .chain(
+            reader
+                .info()
+                .utf8_text
+                .iter()
+                .filter_map(|chunk| Some((chunk.keyword.clone(), chunk.get_text().ok()?))),
+        )
+        .collect())
+}
+
+/// Whether a cached thumbnail still belongs to the file with this uri and modification time
+fn is_valid(metadata: &HashMap<String, String>, uri: &str, mtime: u64) -> bool {
+    metadata
+        .get("Thumb::MTime")
+        .and_then(|t| t.parse::<u64>().ok())
+        == Some(mtime)
+        && metadata.get("Thumb::URI").map(|u| u == uri).unwrap_or(true)
+}
+
+/// The cached thumbnail of `path`, unless it is missing or outdated
+pub fn load_cached(path: &Path, size: CacheSize) -> Option<(RgbaImage, ThumbnailInfo)> {
+    let uri = file_uri(path).ok()?;
+    let mtime = modified_secs(path).ok()?;
+    let file_size = std::fs::metadata(path).ok()?.len();
+    let cached = cache_root()
+        .ok()?
+        .join(size.dir_name())
+        .join(cache_file_name(&uri));
+    let metadata = read_metadata(&cached).ok()?;
+
+    if !is_valid(&metadata, &uri, mtime)
+        || metadata
+            .get("Thumb::Size")
+            .map(|s| s != &file_size.to_string())
+            .unwrap_or_default()
+    {
+        debug!("Cached thumbnail of {} is outdated", path.display());
+        return None;
+    }
+    let dimension = |key: &str| metadata.get(key).and_then(|v| v.parse::<u32>().ok());
+    let dimensions = dimension("Thumb::Image::Width").zip(dimension("Thumb::Image::Height"));
+
+    let thumbnail = image::open(&cached).ok()?.into_rgba8();
+    Some((
+        thumbnail,
+        ThumbnailInfo {
+            dimensions,
+            file_size,
+        },
+    ))
+}
+
+/// Create a cache directory, private to the user as the spec requires
+fn create_private_dir(dir: &Path) -> Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    Ok(builder.create(dir)?)
+}
+
+/// Write a thumbnail png with the metadata other applications use to validate it.
+/// It is written to a temporary file first, so readers never see a partial file.
+fn store(
+    thumbnail: &RgbaImage,
+    target: &Path,
+    uri: &str,
+    mtime: u64,
+    info: Option<ThumbnailInfo>,
+) -> Result<()> {
+    let dir = target.parent().ok_or(anyhow!("Invalid cache path"))?;
+    create_private_dir(dir)?;
+    let temp = target.with_extension(format!("png.{}.tmp", std::process::id()));
+
+    {
+        let file = File::create(&temp)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        }
+        let mut encoder =
+            png::Encoder::new(BufWriter::new(file), thumbnail.width(), thumbnail.height());
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.add_text_chunk("Thumb::URI".into(), uri.into())?;
+        encoder.add_text_chunk("Thumb::MTime".into(), mtime.to_string())?;
+        if let Some(info) = info {
+            encoder.add_text_chunk("Thumb::Size".into(), info.file_size.to_string())?;
+            if let Some((width, height)) = info.dimensions {
+                encoder.add_text_chunk("Thumb::Image::Width".into(), width.to_string())?;
+                encoder.add_text_chunk("Thumb::Image::Height".into(), height.to_string())?;
+            }
+        }
+        encoder.add_text_chunk(
+            "Software".into(),
+            format!("oculante {}", env!("CARGO_PKG_VERSION")),
+        )?;
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(thumbnail.as_raw())?;
+    }
+
+    if let Err(e) = std::fs::rename(&temp, target) {
+        _ = std::fs::remove_file(&temp);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Return the cached thumbnail of `path`, generating all cache sizes if needed.
+/// Files that fail to load are recorded, so they are not tried again until they change.
+pub fn cache_thumbnail(path: &Path, size: CacheSize) -> Result<(RgbaImage, ThumbnailInfo)> {
+    if let Some(cached) = load_cached(path, size) {
+        return Ok(cached);
+    }
+
+    let uri = file_uri(path)?;
+    let mtime = modified_secs(path)?;
+    let root = cache_root()?;
+    let name = cache_file_name(&uri);
+    let fail_path = root.join("fail").join(FAIL_DIR).join(&name);
+    if let Ok(metadata) = read_metadata(&fail_path) {
+        if is_valid(&metadata, &uri, mtime) {
+            bail!("{} could not be loaded before", path.display());
+        }
+    }
+
+    let frame = match open_image(&path.to_path_buf()).and_then(|col| {
+        col.frames
+            .into_iter()
+            .next()
+            .ok_or(anyhow!("No image in {}", path.display()))
+    }) {
+        Ok(frame) => frame,
+        Err(e) => {
+            if let Err(store_error) = store(&RgbaImage::new(1, 1), &fail_path, &uri, mtime, None) {
+                error!("Can't record failed thumbnail: {store_error}");
+            }
+            return Err(e);
+        }
+    };
+
+    let info = ThumbnailInfo {
+        dimensions: Some(frame.buffer.dimensions()),
+        file_size: std::fs::metadata(path)?.len(),
+    };
+    let img = DynamicImage::ImageRgba8(frame.buffer);
+    let mut requested = None;
+    for cache_size in [CacheSize::Normal, CacheSize::Large] {
+        let thumbnail = fit(&img, cache_size.pixels());
+        let target = root.join(cache_size.dir_name()).join(&name);
+        // a cache that can't be written should not prevent showing the thumbnail
+        if let Err(e) = store(&thumbnail, &target, &uri, mtime, Some(info)) {
+            error!("Can't write thumbnail {}: {e}", target.display());
+        }
+        if cache_size == size {
+            requested = Some(thumbnail);
+        }
+    }
+    Ok((requested.unwrap_or_else(|| fit(&img, size.pixels())), info))
+}
+
+/// Fill the thumbnail cache for all images in `dir`
+pub fn pregenerate(dir: &Path) -> Result<()> {
+    if !dir.is_dir() {
+        bail!("{} is not a directory", dir.display());
+    }
+    let files = list_folder(dir, SortOrder::Name, false, &Default::default(), 0);
+    info!("Generating thumbnails for {} images", files.len());
+
+    let up_to_date = AtomicUsize::new(0);
+    let failed = AtomicUsize::new(0);
+    files.par_iter().for_each(|path| {
+        if load_cached(path, CacheSize::Normal).is_some()
+            && load_cached(path, CacheSize::Large).is_some()
+        {
+            up_to_date.fetch_add(1, Ordering::Relaxed);
+        } else if let Err(e) = cache_thumbnail(path, CacheSize::Large) {
+            eprintln!("{}: {e}", path.display());
+            failed.fetch_add(1, Ordering::Relaxed);
+        }
+    });
+
+    let up_to_date = up_to_date.into_inner();
+    let failed = failed.into_inner();
+    println!(
+        "{} thumbnails generated, {up_to_date} up to date, {failed} failed",
+        files.len() - up_to_date - failed
+    );
+    Ok(())
+}
+
+/// Human readable file size
+pub fn format_size(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024. && unit < units.len() - 1 {
+        size /= 1024.;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", units[unit])
+    }
+}
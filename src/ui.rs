@@ -1,16 +1,34 @@
 use crate::{
+    background::BackgroundMode,
+    compare::{Anchor, BeforeAfterMode, CompareMode},
+    diff::Metrics,
+    export::{animation_frames, export_animation, export_sequence, ANIMATION_FORMATS},
+    file_actions::{self, FileAction, QUICK_FOLDERS},
     image_editing::{process_pixels, Channel, ImageOperation, ScaleFilter},
+    ktx::open_ktx,
+    measure::MeasureMode,
+    minimap,
+    navigation::{list_folder, SortOrder},
+    orientation,
     paint::PaintStroke,
-    shortcuts::{keypresses_as_string, lookup},
+    pixel_grid::{visible_pixels, VALUES_ZOOM},
+    sequence,
+    shortcuts::{self, keypresses_as_string, lookup, InputEvent},
+    svg::{is_svg, load_svg, render_svg},
+    thumbnails::{format_size, Thumbnail, THUMB_SIZE},
     update,
     utils::{
-        disp_col, disp_col_norm, highlight_bleed, highlight_semitrans, send_extended_info,
-        ImageExt, OculanteState,
+        disp_col, disp_col_norm, highlight_bleed, highlight_semitrans, img_shift, save_image,
+        send_extended_info, Frame, ImageExt, OculanteState, PlayerCommand,
     },
+    xmp::{self, ColorLabel, XmpData},
+    zoom::{self, FitMode, MAX_SCALE, MIN_SCALE, ZOOM_LEVELS},
 };
+use anyhow::Result;
 use egui::plot::Plot;
 use image::RgbaImage;
-use log::{debug, info};
+use log::{debug, error, info};
+use nalgebra::Vector2;
 use notan::{
     egui::{
         self,
@@ -19,7 +37,13 @@ use notan::{
     },
     prelude::{App, Graphics},
 };
-use std::{collections::HashSet, ops::RangeInclusive, time::Instant};
+use std::{
+    collections::HashSet,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+use strum::IntoEnumIterator;
 
 #[cfg(feature = "turbo")]
 use crate::image_editing::{cropped_range, lossless_tx};
@@ -126,6 +150,19 @@ pub fn info_ui(ctx: &Context, state: &mut OculanteState, gfx: &mut Graphics) {
             state.sampled_color = [p[0] as f32, p[1] as f32, p[2] as f32, p[3] as f32];
         }
     }
+    if let (Some(first), true) = (&state.current_image, state.compare.active) {
+        state.compare.receive_metrics();
+        state.compare.request_metrics(first);
+    }
+    if let Some(img) = &state.compare.image {
+        let pos = state.compare.cursor_relative;
+        state.compare.sampled_color = if pos.x < 0.0 || pos.y < 0.0 {
+            None
+        } else {
+            img.get_pixel_checked(pos.x as u32, pos.y as u32)
+                .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32, p[3] as f32])
+        };
+    }
 
     egui::SidePanel::left("side_panel").show(&ctx, |ui| {
 
@@ -204,6 +241,34 @@ pub fn info_ui(ctx: &Context, state: &mut OculanteState, gfx: &mut Graphics) {
                     );
                     ui.end_row();
 
+                    if state.compare.image.is_some() {
+                        let compare_color = match state.compare.sampled_color {
+                            Some(color) => disp_col(color),
+                            None => "outside".into(),
+                        };
+                        ui.label_i("◫ RGBA");
+                        ui.label(
+                            RichText::new(compare_color)
+                                .monospace()
+                                .background_color(Color32::from_rgba_unmultiplied(255, 255, 255, 6)),
+                        )
+                        .on_hover_text("Second image at the same position");
+                        ui.end_row();
+
+                        ui.label_i("◫ Pos");
+                        ui.label(
+                            RichText::new(format!(
+                                "{:.0},{:.0}",
+                                state.compare.cursor_relative.x, state.compare.cursor_relative.y
+                            ))
+                            .monospace()
+                            .background_color(Color32::from_rgba_unmultiplied(255, 255, 255, 6)),
+                        );
+                        ui.end_row();
+
+                        metrics_ui(state.compare.metrics.as_ref(), ui);
+                    }
+
                     ui.label_i(" UV");
                     ui.label(
                         RichText::new(format!("{:.3},{:.3}", uv_center.0, 1.0 - uv_center.1))
@@ -254,7 +319,10 @@ pub fn info_ui(ctx: &Context, state: &mut OculanteState, gfx: &mut Graphics) {
                             .on_hover_text("Highlight pixels with zero alpha and color information")
                             .clicked()
                         {
-                            state.current_texture = highlight_bleed(img).to_texture(gfx);
+                            state.current_texture = highlight_bleed(img).to_texture_with_filter(
+                                gfx,
+                                state.persistent_settings.pixel_grid.mag_filter(),
+                            );
                         }
                         if ui
                             .button("Show semi-transparent pixels")
@@ -263,10 +331,16 @@ pub fn info_ui(ctx: &Context, state: &mut OculanteState, gfx: &mut Graphics) {
                             )
                             .clicked()
                         {
-                            state.current_texture = highlight_semitrans(img).to_texture(gfx);
+                            state.current_texture = highlight_semitrans(img).to_texture_with_filter(
+                                gfx,
+                                state.persistent_settings.pixel_grid.mag_filter(),
+                            );
                         }
                         if ui.button("Reset image").clicked() {
-                            state.current_texture = img.to_texture(gfx);
+                            state.current_texture = img.to_texture_with_filter(
+                                gfx,
+                                state.persistent_settings.pixel_grid.mag_filter(),
+                            );
                         }
 
                     }
@@ -278,6 +352,22 @@ pub fn info_ui(ctx: &Context, state: &mut OculanteState, gfx: &mut Graphics) {
                 ui.slider_styled(&mut state.tiling, 1..=10);
                 ui.label("Image tiling");
             });
+
+            if state.mip_count > 1 {
+                ui.horizontal(|ui| {
+                    if ui.slider_styled(&mut state.mip_level, 0..=state.mip_count - 1).changed() {
+                        if let Some(p) = &state.current_path {
+                            match open_ktx(p, state.mip_level) {
+                                Ok(img) => {
+                                    let _ = state.player.image_sender.send(Frame::new_reset(img));
+                                }
+                                Err(e) => state.message = Some(e.to_string()),
+                            }
+                        }
+                    }
+                    ui.label("Mip level");
+                });
+            }
             advanced_ui(ui, state);
 
         });
@@ -330,6 +420,78 @@ pub fn settings_ui(app: &mut App, ctx: &Context, state: &mut OculanteState) {
                     _ = state.persistent_settings.save()
                 }
 
+                if ui
+                    .checkbox(&mut state.persistent_settings.auto_reload, "Reload changed images")
+                    .on_hover_text("Show the new version when the current image is changed on disk, keeping zoom and position.")
+                    .changed()
+                {
+                    _ = state.persistent_settings.save()
+                }
+
+                if ui
+                    .checkbox(&mut state.persistent_settings.restore_session, "Restore last session")
+                    .on_hover_text("Show the last image on startup, with the same zoom, channel and panels.")
+                    .changed()
+                {
+                    _ = state.persistent_settings.save()
+                }
+
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_source("fit_mode")
+                        .selected_text(state.persistent_settings.fit_mode.to_string())
+                        .show_ui(ui, |ui| {
+                            for mode in FitMode::iter() {
+                                if ui
+                                    .selectable_value(
+                                        &mut state.persistent_settings.fit_mode,
+                                        mode,
+                                        mode.to_string(),
+                                    )
+                                    .changed()
+                                {
+                                    _ = state.persistent_settings.save();
+                                }
+                            }
+                        });
+                    ui.label("Zoom of new images");
+                });
+
+                if ui
+                    .checkbox(&mut state.persistent_settings.minimap, "Minimap")
+                    .on_hover_text("Show an overview when the image does not fit the window. Click or drag in it to move the view.")
+                    .changed()
+                {
+                    _ = state.persistent_settings.save()
+                }
+
+                if ui
+                    .checkbox(&mut state.persistent_settings.keep_orientation, "Remember rotation per image")
+                    .on_hover_text("Show images again with the view rotation and mirroring they had last time")
+                    .changed()
+                {
+                    _ = state.persistent_settings.save()
+                }
+
+                ui.collapsing("Folder navigation", |ui| {
+                    folder_navigation_ui(state, ui);
+                });
+
+                ui.collapsing("Quick folders", |ui| {
+                    quick_folders_ui(state, ui);
+                });
+
+                ui.collapsing("Slideshow", |ui| {
+                    slideshow_settings_ui(state, ui);
+                });
+
+                ui.collapsing("Background", |ui| {
+                    background_settings_ui(state, ui);
+                });
+
+                ui.collapsing("Pixels", |ui| {
+                    pixel_settings_ui(state, ui);
+                });
+
                 if ui.link("Visit github repo").on_hover_text("Check out the source code, request a feature, submit a bug or leave a star if you like it!").clicked() {
                     _ = webbrowser::open("https://github.com/woelper/oculante");
                 }
@@ -359,6 +521,259 @@ pub fn settings_ui(app: &mut App, ctx: &Context, state: &mut OculanteState) {
     }
 }
 
+/// Sort order and filter used when going to the next or previous image
+fn folder_navigation_ui(state: &mut OculanteState, ui: &mut Ui) {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label("Sort by");
+        egui::ComboBox::from_id_source("sort_order")
+            .selected_text(state.persistent_settings.sort_order.to_string())
+            .show_ui(ui, |ui| {
+                for order in SortOrder::iter() {
+                    changed |= ui
+                        .selectable_value(
+                            &mut state.persistent_settings.sort_order,
+                            order,
+                            order.to_string(),
+                        )
+                        .changed();
+                }
+            });
+        changed |= ui
+            .checkbox(&mut state.persistent_settings.sort_descending, "Descending")
+            .changed();
+        if state.persistent_settings.sort_order == SortOrder::Shuffle
+            && ui.button("🔀 Reshuffle").clicked()
+        {
+            state.folder_listing.reshuffle();
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label("Filter");
+        changed |= ui
+            .text_edit_singleline(&mut state.persistent_settings.name_filter)
+            .on_hover_text("Only show images whose file name contains this text")
+            .changed();
+    });
+    ui.horizontal(|ui| {
+        ui.label("Rated at least");
+        changed |= ui
+            .add(egui::Slider::new(
+                &mut state.persistent_settings.min_rating,
+                0..=5,
+            ))
+            .changed();
+    });
+    ui.horizontal(|ui| {
+        ui.label("Tag");
+        changed |= ui
+            .text_edit_singleline(&mut state.persistent_settings.tag_filter)
+            .on_hover_text("Only show images with this tag")
+            .changed();
+    });
+    if changed {
+        _ = state.persistent_settings.save();
+    }
+}
+
+/// Target folders for moving and copying images with the number keys
+fn quick_folders_ui(state: &mut OculanteState, ui: &mut Ui) {
+    let folders = &mut state.persistent_settings.quick_folders;
+    folders.resize(QUICK_FOLDERS, PathBuf::new());
+    let mut changed = false;
+    egui::Grid::new("quick_folders").show(ui, |ui| {
+        for (index, folder) in folders.iter_mut().enumerate() {
+            ui.label(format!("{}", index + 1));
+            let mut text = folder.display().to_string();
+            if ui
+                .text_edit_singleline(&mut text)
+                .on_hover_text("Folder to move images to with this number, or copy with Shift")
+                .changed()
+            {
+                *folder = PathBuf::from(text);
+                changed = true;
+            }
+            ui.end_row();
+        }
+    });
+    if changed {
+        _ = state.persistent_settings.save();
+    }
+}
+
+fn slideshow_settings_ui(state: &mut OculanteState, ui: &mut Ui) {
+    let settings = &mut state.persistent_settings.slideshow;
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label("Show each image for");
+        changed |= ui
+            .add(
+                egui::DragValue::new(&mut settings.interval)
+                    .clamp_range(0.5..=3600.0)
+                    .speed(0.1)
+                    .suffix(" s"),
+            )
+            .changed();
+    });
+    changed |= ui.checkbox(&mut settings.repeat, "Loop").changed();
+    changed |= ui.checkbox(&mut settings.shuffle, "Shuffle").changed();
+    changed |= ui
+        .checkbox(&mut settings.fade, "Fade between images")
+        .changed();
+    changed |= ui
+        .checkbox(&mut settings.fullscreen, "Fullscreen")
+        .on_hover_text("Switch to fullscreen while the slideshow runs")
+        .changed();
+    if changed {
+        _ = state.persistent_settings.save();
+    }
+}
+
+fn background_settings_ui(state: &mut OculanteState, ui: &mut Ui) {
+    let settings = &mut state.persistent_settings.background;
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        for mode in BackgroundMode::iter() {
+            changed |= ui
+                .selectable_value(&mut settings.mode, mode, mode.to_string())
+                .changed();
+        }
+    });
+    ui.horizontal(|ui| {
+        changed |= ui.color_edit_button_srgb(&mut settings.color).changed();
+        ui.label("Window color");
+    });
+    ui.horizontal(|ui| {
+        for color in &mut settings.checker_colors {
+            changed |= ui.color_edit_button_srgb(color).changed();
+        }
+        ui.label("Checker colors");
+    });
+    changed |= ui
+        .add(egui::Slider::new(&mut settings.checker_size, 2..=128).text("Checker size"))
+        .changed();
+    if changed {
+        _ = state.persistent_settings.save();
+    }
+}
+
+fn pixel_settings_ui(state: &mut OculanteState, ui: &mut Ui) {
+    let settings = &mut state.persistent_settings.pixel_grid;
+    let mut changed = false;
+    if ui
+        .checkbox(&mut settings.nearest, "Sharp pixels when zoomed in")
+        .on_hover_text("Use nearest neighbour instead of linear sampling for magnification")
+        .changed()
+    {
+        state.refresh_texture = true;
+        changed = true;
+    }
+    ui.horizontal(|ui| {
+        changed |= ui.checkbox(&mut settings.grid, "Pixel grid from").changed();
+        changed |= ui
+            .add(
+                egui::DragValue::new(&mut settings.grid_zoom)
+                    .clamp_range(2.0..=100.0)
+                    .speed(0.1)
+                    .suffix("x"),
+            )
+            .changed();
+    });
+    changed |= ui
+        .checkbox(&mut settings.values, "Pixel values")
+        .on_hover_text(format!(
+            "Show the RGBA values inside each pixel from {VALUES_ZOOM}x zoom"
+        ))
+        .changed();
+    if changed {
+        _ = state.persistent_settings.save();
+    }
+}
+
+/// Grid lines between pixels and their values, when zoomed in far enough
+pub fn pixel_grid_ui(ctx: &Context, state: &OculanteState) {
+    let settings = &state.persistent_settings.pixel_grid;
+    let img = match &state.current_image {
+        Some(img) if state.tiling < 2 && !state.thumbnail_grid.enabled => img,
+        _ => return,
+    };
+    let show_grid = settings.grid && state.scale >= settings.grid_zoom;
+    let show_values = settings.values && state.scale >= VALUES_ZOOM;
+    if !show_grid && !show_values {
+        return;
+    }
+    let painter = ctx.layer_painter(egui::LayerId::background());
+    let orientation = orientation::current(state);
+    let size = img.size_vec();
+    let view_size = orientation.view_size(size);
+    let (view_columns, view_rows) = visible_pixels(
+        state.offset,
+        state.scale,
+        (view_size.x as u32, view_size.y as u32),
+        state.window_size,
+    );
+    // the same pixels in image coordinates
+    let a = orientation.to_image(
+        Vector2::new(view_columns.start as f32, view_rows.start as f32),
+        size,
+    );
+    let b = orientation.to_image(
+        Vector2::new(view_columns.end as f32, view_rows.end as f32),
+        size,
+    );
+    let columns = a.x.min(b.x) as u32..a.x.max(b.x) as u32;
+    let rows = a.y.min(b.y) as u32..a.y.max(b.y) as u32;
+    let screen = |x: f32, y: f32| {
+        let pos = state.offset + orientation.to_view(Vector2::new(x, y), size) * state.scale;
+        Pos2::new(pos.x, pos.y)
+    };
+
+    if show_grid {
+        let stroke = Stroke::new(1.0, Color32::from_black_alpha(100));
+        for x in columns.start..=columns.end {
+            painter.line_segment(
+                [
+                    screen(x as f32, rows.start as f32),
+                    screen(x as f32, rows.end as f32),
+                ],
+                stroke,
+            );
+        }
+        for y in rows.start..=rows.end {
+            painter.line_segment(
+                [
+                    screen(columns.start as f32, y as f32),
+                    screen(columns.end as f32, y as f32),
+                ],
+                stroke,
+            );
+        }
+    }
+
+    if show_values {
+        let font = FontId::monospace((state.scale / 6.0).min(16.0));
+        for y in rows.clone() {
+            for x in columns.clone() {
+                let p = img.get_pixel(x, y);
+                let luma = 0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32;
+                // transparent pixels show the background, which is dark by default
+                let color = if luma > 128.0 && p[3] > 128 {
+                    Color32::BLACK
+                } else {
+                    Color32::WHITE
+                };
+                painter.text(
+                    screen(x as f32 + 0.5, y as f32 + 0.5),
+                    Align2::CENTER_CENTER,
+                    format!("{}\n{}\n{}\n{}", p[0], p[1], p[2], p[3]),
+                    font.clone(),
+                    color,
+                );
+            }
+        }
+    }
+}
+
 pub fn advanced_ui(ui: &mut Ui, state: &mut OculanteState) {
     if let Some(info) = &state.image_info {
         egui::Grid::new("extended").show(ui, |ui| {
@@ -539,7 +954,10 @@ pub fn edit_ui(ctx: &Context, state: &mut OculanteState, gfx: &mut Graphics) {
                         {
                             if let Some(img) = &state.current_image {
                                 state.image_dimension = img.dimensions();
-                                state.current_texture = img.to_texture(gfx);
+                                state.current_texture = img.to_texture_with_filter(
+                                    gfx,
+                                    state.persistent_settings.pixel_grid.mag_filter(),
+                                );
                             }
                         }
                         if ui
@@ -553,6 +971,22 @@ pub fn edit_ui(ctx: &Context, state: &mut OculanteState, gfx: &mut Graphics) {
                         }
                     });
                     ui.end_row();
+
+                    ui.label_i("◧ Before/after");
+                    egui::ComboBox::from_id_source("before_after")
+                        .selected_text(state.before_after.mode.to_string())
+                        .show_ui(ui, |ui| {
+                            for mode in BeforeAfterMode::iter() {
+                                ui.selectable_value(
+                                    &mut state.before_after.mode,
+                                    mode,
+                                    mode.to_string(),
+                                );
+                            }
+                        })
+                        .response
+                        .on_hover_text("Show the original image next to the edited one");
+                    ui.end_row();
                 });
 
             ui.vertical_centered_justified(|ui| {
@@ -745,6 +1179,7 @@ pub fn edit_ui(ctx: &Context, state: &mut OculanteState, gfx: &mut Graphics) {
                     if let Some(img) = &mut state.current_image {
                         *img = state.edit_state.result_pixel_op.clone();
                         state.edit_state = Default::default();
+                        state.before_after.invalidate();
                         // state.image_dimension = img.dimensions();
                         pixels_changed = true;
                         image_changed = true;
@@ -814,7 +1249,10 @@ pub fn edit_ui(ctx: &Context, state: &mut OculanteState, gfx: &mut Graphics) {
                             state.edit_state.result_pixel_op.update_texture(gfx, tex);
                         } else {
                             state.current_texture =
-                                state.edit_state.result_pixel_op.to_texture(gfx);
+                                state.edit_state.result_pixel_op.to_texture_with_filter(
+                                    gfx,
+                                    state.persistent_settings.pixel_grid.mag_filter(),
+                                );
                         }
                     }
                 }
@@ -897,7 +1335,7 @@ pub fn edit_ui(ctx: &Context, state: &mut OculanteState, gfx: &mut Graphics) {
                         .selected_text(&state.edit_state.export_extension)
                         .width(ui.available_width() - ui.style().spacing.item_spacing.x)
                         .show_ui(ui, |ui| {
-                            for f in ["png", "jpg", "bmp", "webp", "tif", "tga"] {
+                            for f in ["png", "jpg", "bmp", "webp", "tif", "tga", "qoi"] {
                                 ui.selectable_value(
                                     &mut state.edit_state.export_extension,
                                     f.to_string(),
@@ -910,6 +1348,10 @@ pub fn edit_ui(ctx: &Context, state: &mut OculanteState, gfx: &mut Graphics) {
                 #[cfg(feature = "turbo")]
                 jpg_lossless_ui(state, ui);
 
+                svg_export_ui(state, ui);
+
+                animation_export_ui(state, ui);
+
                 if let Some(p) = &state.current_path {
                     let text = if p
                         .with_extension(&state.edit_state.export_extension)
@@ -921,10 +1363,12 @@ pub fn edit_ui(ctx: &Context, state: &mut OculanteState, gfx: &mut Graphics) {
                     };
 
                     if ui.button(text).on_hover_text("Save the image. This will create a new file or overwrite.").clicked() {
-                        _ = state
-                            .edit_state
-                            .result_pixel_op
-                            .save(p.with_extension(&state.edit_state.export_extension));
+                        if let Err(e) = save_image(
+                            &state.edit_state.result_pixel_op,
+                            &p.with_extension(&state.edit_state.export_extension),
+                        ) {
+                            state.message = Some(e.to_string());
+                        }
                     }
 
                     if ui.button("💾 Save edits").on_hover_text("Saves an .oculante metafile in the same directory as the image. This file will contain all edits and will be restored automatically if you open the image again. This leaves the original image unmodified and allows you to continue editing later.").clicked() {
@@ -946,67 +1390,1267 @@ pub fn edit_ui(ctx: &Context, state: &mut OculanteState, gfx: &mut Graphics) {
         });
 }
 
-// TODO redo as impl UI
-pub fn tooltip(r: Response, tooltip: &str, hotkey: &str, _ui: &mut Ui) -> Response {
-    r.on_hover_ui(|ui| {
-        let avg = (ui.style().visuals.selection.bg_fill.r() as i32
-            + ui.style().visuals.selection.bg_fill.g() as i32
-            + ui.style().visuals.selection.bg_fill.b() as i32)
-            / 3;
-        let contrast_color: u8 = if avg > 128 { 0 } else { 255 };
+/// Look for the numbered sequence the current file belongs to, or hide it again
+pub fn toggle_sequence(state: &mut OculanteState) {
+    if state.sequence.take().is_some() {
+        return;
+    }
+    state.sequence = state
+        .current_path
+        .as_ref()
+        .and_then(|p| sequence::detect_sequence(p));
+    if state.sequence.is_none() {
+        state.message = Some("No numbered image sequence found".into());
+    }
+}
+
+/// Offers to play the numbered sequence the current file belongs to
+pub fn sequence_ui(ctx: &Context, state: &mut OculanteState) {
+    let sequence = match &state.sequence {
+        Some(sequence) => sequence.clone(),
+        None => return,
+    };
+
+    egui::TopBottomPanel::bottom("sequence").show(ctx, |ui| {
         ui.horizontal(|ui| {
-            ui.label(tooltip);
-            ui.label(
-                RichText::new(hotkey)
-                    .monospace()
-                    .color(Color32::from_gray(contrast_color))
-                    .background_color(ui.style().visuals.selection.bg_fill),
+            ui.label(format!("🎞 Sequence of {} frames", sequence.files.len()));
+            ui.add(
+                egui::DragValue::new(&mut state.sequence_fps)
+                    .clamp_range(1.0..=120.0)
+                    .suffix(" fps"),
             );
+            if ui
+                .button("▶ Play sequence")
+                .on_hover_text("Play all frames of the sequence as animation")
+                .clicked()
+            {
+                state.player.load_sequence(
+                    sequence.paths(),
+                    state.sequence_fps,
+                    state.message_channel.0.clone(),
+                );
+            }
+            if !sequence.missing.is_empty() {
+                ui.colored_label(
+                    Color32::from_rgb(255, 180, 0),
+                    format!(
+                        "⚠ {} missing: {}",
+                        sequence.missing.len(),
+                        sequence.missing_summary()
+                    ),
+                );
+            }
+            if unframed_button("✖", ui).on_hover_text("Close").clicked() {
+                state.sequence = None;
+            }
         });
-    })
+    });
 }
 
-// TODO redo as impl UI
-pub fn unframed_button(text: impl Into<WidgetText>, ui: &mut Ui) -> Response {
-    ui.add(egui::Button::new(text).frame(false))
+/// Playback controls and timeline for animations
+pub fn playback_ui(ctx: &Context, state: &mut OculanteState) {
+    let status = match state.player.status() {
+        Some(status) => status,
+        None => return,
+    };
+    let shortcuts = &state.persistent_settings.shortcuts;
+
+    egui::TopBottomPanel::bottom("playback").show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            if tooltip(
+                unframed_button("⏴", ui),
+                "Previous frame",
+                &lookup(shortcuts, &InputEvent::PreviousFrame),
+                ui,
+            )
+            .clicked()
+            {
+                state.player.command(PlayerCommand::Step(-1));
+            }
+
+            let (icon, description) = if status.paused {
+                ("▶", "Play")
+            } else {
+                ("⏸", "Pause")
+            };
+            if tooltip(
+                unframed_button(icon, ui),
+                description,
+                &lookup(shortcuts, &InputEvent::PauseAnimation),
+                ui,
+            )
+            .clicked()
+            {
+                state.player.command(if status.paused {
+                    PlayerCommand::Resume
+                } else {
+                    PlayerCommand::Pause
+                });
+            }
+
+            if tooltip(
+                unframed_button("⏵", ui),
+                "Next frame",
+                &lookup(shortcuts, &InputEvent::NextFrame),
+                ui,
+            )
+            .clicked()
+            {
+                state.player.command(PlayerCommand::Step(1));
+            }
+
+            let mut speed = status.speed;
+            if ui
+                .add(
+                    egui::DragValue::new(&mut speed)
+                        .clamp_range(0.1..=8.0)
+                        .speed(0.01)
+                        .suffix("×"),
+                )
+                .on_hover_text("Playback speed")
+                .changed()
+            {
+                state.player.command(PlayerCommand::Speed(speed));
+            }
+
+            let last = status.frame_count() - 1;
+            ui.label(format!(
+                "{}/{} {}ms",
+                status.index + 1,
+                status.frame_count(),
+                status.delays[status.index]
+            ));
+
+            // Let the timeline fill the remaining space
+            ui.spacing_mut().slider_width = ui.available_width();
+            let mut index = status.index;
+            if ui
+                .add(egui::Slider::new(&mut index, 0..=last).show_value(false))
+                .changed()
+            {
+                state.player.command(PlayerCommand::Seek(index));
+            }
+        });
+    });
 }
 
-pub fn unframed_button_colored(text: impl Into<String>, is_colored: bool, ui: &mut Ui) -> Response {
-    if is_colored {
-        ui.add(
-            egui::Button::new(RichText::new(text).color(ui.style().visuals.selection.bg_fill))
-                .frame(false),
-        )
-    } else {
-        ui.add(egui::Button::new(RichText::new(text)).frame(false))
+/// The images shown in the thumbnail grid, in navigation order
+pub fn grid_files(state: &mut OculanteState) -> Vec<PathBuf> {
+    match &state.current_path {
+        Some(p) => state
+            .folder_listing
+            .files(
+                p,
+                state.persistent_settings.sort_order,
+                state.persistent_settings.sort_descending,
+                &state.persistent_settings.file_filter(),
+            )
+            .to_vec(),
+        None => vec![],
     }
 }
 
-pub fn stroke_ui(
-    stroke: &mut PaintStroke,
-    brushes: &Vec<RgbaImage>,
-    ui: &mut Ui,
-    gfx: &mut Graphics,
-) -> Response {
-    let mut combined_response = ui.color_edit_button_rgba_unmultiplied(&mut stroke.color);
-
-    let r = ui
-        .checkbox(&mut stroke.fade, "")
-        .on_hover_text("Fade out the stroke over its path");
-    if r.changed() {
-        combined_response.changed = true;
+/// Open an image picked in the thumbnail grid and return to the image view
+pub fn open_from_grid(state: &mut OculanteState, path: PathBuf) {
+    state.thumbnail_grid.enabled = false;
+    if state.current_path.as_ref() != Some(&path) {
+        state.is_loaded = false;
+        state.player.load(&path, state.message_channel.0.clone());
+        state.current_path = Some(path);
     }
-    if r.hovered() {
-        combined_response.hovered = true;
+}
+
+/// Open a recent image or bookmark, or the first image of a recent folder
+fn open_recent(state: &mut OculanteState, path: &Path) {
+    let path = if path.is_dir() {
+        let settings = &state.persistent_settings;
+        match list_folder(
+            path,
+            settings.sort_order,
+            settings.sort_descending,
+            &settings.file_filter(),
+            0,
+        )
+        .into_iter()
+        .next()
+        {
+            Some(first) => first,
+            None => {
+                state.message = Some(format!("No images in {}", path.display()));
+                return;
+            }
+        }
+    } else {
+        path.to_path_buf()
+    };
+    state.thumbnail_grid.enabled = false;
+    state.is_loaded = false;
+    state.player.load(&path, state.message_channel.0.clone());
+    state.current_path = Some(path);
+}
+
+pub fn toggle_bookmark(state: &mut OculanteState) {
+    if let Some(path) = state.current_path.clone().filter(|p| p.is_file()) {
+        state.message = Some(if state.persistent_settings.toggle_bookmark(&path) {
+            "Bookmarked".into()
+        } else {
+            "Bookmark removed".into()
+        });
+        _ = state.persistent_settings.save();
     }
+}
 
-    let r = ui
-        .checkbox(&mut stroke.flip_random, "")
-        .on_hover_text("Flip brush in X any Y randomly to make stroke less uniform");
-    if r.changed() {
-        combined_response.changed = true;
+/// The zoom in percent, which can be typed in, and a menu of zoom levels and fit modes
+pub fn zoom_ui(state: &mut OculanteState, ui: &mut Ui) {
+    let mut percent = state.scale * 100.0;
+    if ui
+        .add(
+            egui::DragValue::new(&mut percent)
+                // huge images can be fitted below the smallest zoom
+                .clamp_range(MIN_SCALE.min(state.scale) * 100.0..=MAX_SCALE * 100.0)
+                .max_decimals(1)
+                .suffix("%"),
+        )
+        .on_hover_text("Zoom. Drag or click to type an exact value.")
+        .changed()
+    {
+        zoom::zoom_centered(state, percent / 100.0);
     }
-    if r.hovered() {
+    ui.menu_button("🔍", |ui| {
+        for (index, level) in ZOOM_LEVELS.iter().enumerate() {
+            let text = format!("{}%", level * 100.0);
+            if tooltip(
+                ui.button(&text),
+                &text,
+                &lookup(
+                    &state.persistent_settings.shortcuts,
+                    &shortcuts::ZOOM[index],
+                ),
+                ui,
+            )
+            .clicked()
+            {
+                zoom::zoom_centered(state, *level);
+                ui.close_menu();
+            }
+        }
+        ui.separator();
+        let fits = [
+            (FitMode::Fit, None),
+            (FitMode::Width, Some(InputEvent::FitWidth)),
+            (FitMode::Height, Some(InputEvent::FitHeight)),
+            (FitMode::Fill, Some(InputEvent::FillWindow)),
+        ];
+        for (mode, event) in fits {
+            let shortcut = event
+                .map(|e| lookup(&state.persistent_settings.shortcuts, &e))
+                .unwrap_or_default();
+            if tooltip(
+                ui.button(mode.to_string()),
+                &mode.to_string(),
+                &shortcut,
+                ui,
+            )
+            .clicked()
+            {
+                zoom::fit(state, mode);
+                ui.close_menu();
+            }
+        }
+        ui.separator();
+        let shortcuts = &state.persistent_settings.shortcuts;
+        let rotate_left = lookup(shortcuts, &InputEvent::RotateCounterClockwise);
+        let rotate_right = lookup(shortcuts, &InputEvent::RotateClockwise);
+        let mirror_h = lookup(shortcuts, &InputEvent::MirrorHorizontal);
+        let mirror_v = lookup(shortcuts, &InputEvent::MirrorVertical);
+        ui.horizontal(|ui| {
+            if tooltip(ui.button("↺"), "Rotate left", &rotate_left, ui).clicked() {
+                rotate_view(state, false);
+            }
+            if tooltip(ui.button("↻"), "Rotate right", &rotate_right, ui).clicked() {
+                rotate_view(state, true);
+            }
+            if tooltip(ui.button("↔"), "Mirror horizontally", &mirror_h, ui).clicked() {
+                mirror_view(state, true);
+            }
+            if tooltip(ui.button("↕"), "Mirror vertically", &mirror_v, ui).clicked() {
+                mirror_view(state, false);
+            }
+            if !state.orientation.is_identity()
+                && ui
+                    .button("Reset")
+                    .on_hover_text("Show the image as it is stored")
+                    .clicked()
+            {
+                orientation::set(state, Default::default());
+            }
+        });
+    })
+    .response
+    .on_hover_text("Zoom levels, fit modes, rotation and mirroring");
+}
+
+/// The whole image with the visible part marked, when it does not fit the window.
+/// Clicking or dragging moves the view there.
+pub fn minimap_ui(ctx: &Context, state: &mut OculanteState, gfx: &mut Graphics) {
+    if !state.persistent_settings.minimap
+        || state.tiling > 1
+        || state.compare.active
+        || state.thumbnail_grid.enabled
+    {
+        return;
+    }
+    let texture = match &state.current_texture {
+        Some(texture) => texture,
+        None => return,
+    };
+    let orientation = orientation::current(state);
+    let size = Vector2::new(texture.width(), texture.height());
+    let view_size = orientation.view_size(size);
+    if !minimap::is_needed(state.offset, state.scale, view_size, state.window_size) {
+        return;
+    }
+    let tex_id = gfx.egui_register_texture(texture);
+    let map_scale = minimap::SIZE / view_size.x.max(view_size.y);
+    let map_size = Vec2::new(view_size.x, view_size.y) * map_scale;
+    // bottom right of the space the panels leave
+    let pos = ctx.available_rect().max - map_size - Vec2::splat(20.0);
+
+    egui::Area::new("minimap").fixed_pos(pos).show(ctx, |ui| {
+        egui::Frame::popup(ui.style()).show(ui, |ui| {
+            let (rect, response) = ui.allocate_exact_size(map_size, Sense::click_and_drag());
+
+            // the corners of the image as shown, with the texture rotated to match
+            let mut mesh = egui::Mesh::with_texture(tex_id);
+            let corners = [
+                Vector2::zeros(),
+                Vector2::new(view_size.x, 0.0),
+                view_size,
+                Vector2::new(0.0, view_size.y),
+            ];
+            for corner in corners {
+                let uv = orientation.to_image(corner, size).component_div(&size);
+                mesh.vertices.push(epaint::Vertex {
+                    pos: rect.min + Vec2::new(corner.x, corner.y) * map_scale,
+                    uv: Pos2::new(uv.x, uv.y),
+                    color: Color32::WHITE,
+                });
+            }
+            mesh.add_triangle(0, 1, 2);
+            mesh.add_triangle(0, 2, 3);
+            ui.painter().add(Shape::mesh(mesh));
+
+            let (min, extent) = minimap::viewport(state.offset, state.scale, state.window_size);
+            let visible = Rect::from_min_size(
+                rect.min + Vec2::new(min.x, min.y) * map_scale,
+                Vec2::new(extent.x, extent.y) * map_scale,
+            )
+            .intersect(rect);
+            if visible.is_positive() {
+                ui.painter().rect_stroke(
+                    visible,
+                    0.0,
+                    Stroke::new(1.5, ui.style().visuals.selection.bg_fill),
+                );
+            }
+
+            if response.clicked() || response.dragged() {
+                if let Some(pointer) = response.interact_pointer_pos() {
+                    let pos = (pointer - rect.min) / map_scale;
+                    state.offset = minimap::center_on(
+                        Vector2::new(pos.x, pos.y),
+                        state.scale,
+                        state.window_size,
+                    );
+                }
+            }
+        });
+    });
+}
+
+/// Entries of the recent menu. Missing files are shown, but can't be opened.
+pub fn recent_ui(state: &mut OculanteState, ui: &mut Ui) {
+    let mut open = None;
+    let mut list = |ui: &mut Ui, title: &str, paths: &[PathBuf]| {
+        ui.label(RichText::new(title).strong());
+        if paths.is_empty() {
+            ui.label("None yet");
+        }
+        for path in paths {
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| path.display().to_string());
+            if ui
+                .add_enabled(path.exists(), egui::Button::new(name).frame(false))
+                .on_hover_text(path.display().to_string())
+                .clicked()
+            {
+                open = Some(path.clone());
+            }
+        }
+        ui.separator();
+    };
+    let settings = &state.persistent_settings;
+    list(ui, "Bookmarks", &settings.bookmarks);
+    list(ui, "Recent images", &settings.recent_files);
+    list(ui, "Recent folders", &settings.recent_dirs);
+
+    if ui.button("Clear recent").clicked() {
+        state.persistent_settings.recent_files.clear();
+        state.persistent_settings.recent_dirs.clear();
+        _ = state.persistent_settings.save();
+        ui.close_menu();
+    }
+    if let Some(path) = open {
+        open_recent(state, &path);
+        ui.close_menu();
+    }
+}
+
+/// Show an image of the watched folder. The view is kept, as these are often frames of the same render.
+pub fn show_watched(state: &mut OculanteState, path: PathBuf) {
+    if state.current_image.is_some() {
+        state.keep_view_for = Some(path.clone());
+    } else {
+        state.is_loaded = false;
+    }
+    state.player.load(&path, state.message_channel.0.clone());
+    state.current_path = Some(path);
+}
+
+/// Start or stop watching the folder of the current image
+pub fn toggle_watch_folder(state: &mut OculanteState) {
+    if state.watch_folder.active {
+        state.watch_folder.stop();
+        return;
+    }
+    let dir = match state.current_path.as_ref().and_then(|p| p.parent()) {
+        Some(dir) if dir.as_os_str().is_empty() => PathBuf::from("."),
+        Some(dir) => dir.to_path_buf(),
+        None => return,
+    };
+    if let Some(newest) = state.watch_folder.start(&dir) {
+        if state.current_path.as_ref() != Some(&newest) {
+            show_watched(state, newest);
+        }
+    }
+}
+
+/// Status of the watch folder mode, with controls to go through recent images
+pub fn watch_folder_ui(ctx: &Context, state: &mut OculanteState) {
+    let dir = match state.watch_folder.dir() {
+        Some(dir) if state.watch_folder.active => dir.display().to_string(),
+        _ => return,
+    };
+
+    egui::TopBottomPanel::bottom("watch_folder").show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            ui.label(format!("👁 Watching {dir}"));
+            let (position, len) = state.watch_folder.history_position();
+            if unframed_button("⏴", ui)
+                .on_hover_text("Older image")
+                .clicked()
+            {
+                if let Some(path) = state.watch_folder.back() {
+                    show_watched(state, path);
+                }
+            }
+            ui.label(format!("{}/{len}", (position + 1).min(len)));
+            if unframed_button("⏵", ui)
+                .on_hover_text("Newer image")
+                .clicked()
+            {
+                if let Some(path) = state.watch_folder.forward() {
+                    show_watched(state, path);
+                }
+            }
+            if !state.watch_folder.is_following()
+                && ui
+                    .button("⏭ Show newest")
+                    .on_hover_text("Show new images again as they arrive")
+                    .clicked()
+            {
+                if let Some(path) = state.watch_folder.follow() {
+                    show_watched(state, path);
+                }
+            }
+            if ui.button("Stop watching").clicked() {
+                state.watch_folder.stop();
+            }
+        });
+    });
+}
+
+/// Rows of the info grid with the quality metrics of the compared images
+fn metrics_ui(metrics: Option<&Result<Metrics, String>>, ui: &mut Ui) {
+    let metrics = match metrics {
+        Some(Ok(metrics)) => metrics,
+        Some(Err(e)) => {
+            ui.label_i("◫ Metrics");
+            ui.label(e);
+            ui.end_row();
+            return;
+        }
+        None => {
+            ui.label_i("◫ Metrics");
+            ui.spinner();
+            ui.end_row();
+            return;
+        }
+    };
+    let channels = |values: [f64; 4], precision: usize| {
+        format!(
+            "R {:.p$}  G {:.p$}  B {:.p$}  A {:.p$}",
+            values[0],
+            values[1],
+            values[2],
+            values[3],
+            p = precision
+        )
+    };
+    let rows = [
+        (
+            "PSNR",
+            format!("{:.2} dB", metrics.combined_psnr),
+            channels(metrics.psnr, 2),
+        ),
+        (
+            "SSIM",
+            format!("{:.5}", metrics.combined_ssim),
+            channels(metrics.ssim, 5),
+        ),
+        (
+            "MSE",
+            format!("{:.3}", metrics.combined_mse),
+            channels(metrics.mse, 3),
+        ),
+        (
+            "Max error",
+            metrics.combined_max_error.to_string(),
+            channels(metrics.max_error.map(|e| e as f64), 0),
+        ),
+    ];
+    for (name, combined, per_channel) in rows {
+        ui.label_i(&format!("◫ {name}"));
+        ui.label(
+            RichText::new(combined)
+                .monospace()
+                .background_color(Color32::from_rgba_unmultiplied(255, 255, 255, 6)),
+        )
+        .on_hover_text(per_channel);
+        ui.end_row();
+    }
+}
+
+/// Turn the view by 90°, the image itself stays as it is
+pub fn rotate_view(state: &mut OculanteState, clockwise: bool) {
+    let mut orientation = state.orientation;
+    orientation.rotate(clockwise);
+    orientation::set(state, orientation);
+}
+
+/// Mirror the view left to right, or top to bottom
+pub fn mirror_view(state: &mut OculanteState, horizontal: bool) {
+    let mut orientation = state.orientation;
+    if horizontal {
+        orientation.mirror_horizontal = !orientation.mirror_horizontal;
+    } else {
+        orientation.mirror_vertical = !orientation.mirror_vertical;
+    }
+    orientation::set(state, orientation);
+}
+
+/// Compare the current image with the next one in the folder, or stop comparing
+pub fn toggle_compare(state: &mut OculanteState) {
+    if state.compare.active {
+        state.compare.stop();
+        let mode = state.persistent_settings.fit_mode;
+        zoom::fit(state, mode);
+        return;
+    }
+    let current = match &state.current_path {
+        Some(p) if p.is_file() => p.clone(),
+        _ => return,
+    };
+    let next = img_shift(
+        &current,
+        1,
+        &mut state.folder_listing,
+        &state.persistent_settings,
+    );
+    if next == current {
+        state.compare.active = true;
+        state.message = Some("Drop an image to compare with".into());
+    } else {
+        state.compare.load(&next);
+    }
+}
+
+/// Show another image of the folder as the second image
+fn shift_compare(state: &mut OculanteState, inc: isize) {
+    if let Some(path) = state.compare.path.clone() {
+        let other = img_shift(
+            &path,
+            inc,
+            &mut state.folder_listing,
+            &state.persistent_settings,
+        );
+        state.compare.load(&other);
+    }
+}
+
+/// Settings of the compare mode and the second image's name
+pub fn compare_ui(ctx: &Context, state: &mut OculanteState) {
+    if !state.compare.active {
+        return;
+    }
+
+    egui::TopBottomPanel::bottom("compare").show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            ui.label("◫ Compare with");
+            if unframed_button("⏴", ui)
+                .on_hover_text("Previous image in the folder")
+                .clicked()
+            {
+                shift_compare(state, -1);
+            }
+            let name = match &state.compare.path {
+                Some(path) => path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                None => "Drop an image here".into(),
+            };
+            ui.label(RichText::new(name).monospace());
+            if unframed_button("⏵", ui)
+                .on_hover_text("Next image in the folder")
+                .clicked()
+            {
+                shift_compare(state, 1);
+            }
+            if state.compare.is_loading() {
+                ui.spinner();
+            }
+
+            egui::ComboBox::from_id_source("compare_mode")
+                .selected_text(state.compare.mode.to_string())
+                .show_ui(ui, |ui| {
+                    for mode in CompareMode::iter() {
+                        let previous = state.compare.mode;
+                        if ui
+                            .selectable_value(&mut state.compare.mode, mode, mode.to_string())
+                            .changed()
+                        {
+                            state.compare.invalidate_overlay();
+                            // modes drawing the images on top of each other share the view
+                            if !(previous.is_overlay() && mode.is_overlay()) {
+                                let fit_mode = state.persistent_settings.fit_mode;
+                                zoom::fit(state, fit_mode);
+                            }
+                        }
+                    }
+                });
+            let mut anchor_changed = false;
+            egui::ComboBox::from_id_source("compare_anchor")
+                .selected_text(state.compare.anchor.to_string())
+                .show_ui(ui, |ui| {
+                    for anchor in Anchor::iter() {
+                        anchor_changed |= ui
+                            .selectable_value(&mut state.compare.anchor, anchor, anchor.to_string())
+                            .changed();
+                    }
+                })
+                .response
+                .on_hover_text("Where images of different sizes line up");
+            if anchor_changed {
+                state.compare.invalidate_overlay();
+            }
+
+            match state.compare.mode {
+                CompareMode::Wipe => {
+                    ui.label("Drag the line to move the split");
+                }
+                CompareMode::Flicker => {
+                    let shown = if state.compare.show_second { "B" } else { "A" };
+                    if tooltip(
+                        ui.button(format!("Showing {shown}")),
+                        "Switch images",
+                        &lookup(&state.persistent_settings.shortcuts, &InputEvent::Flicker),
+                        ui,
+                    )
+                    .clicked()
+                    {
+                        state.compare.show_second = !state.compare.show_second;
+                    }
+                }
+                CompareMode::Difference => {
+                    ui.label("Amplify");
+                    if ui
+                        .add(
+                            egui::Slider::new(&mut state.compare.amplification, 1.0..=100.0)
+                                .logarithmic(true),
+                        )
+                        .changed()
+                    {
+                        state.compare.invalidate_overlay();
+                    }
+                }
+                CompareMode::ChangedPixels => {
+                    ui.label("Threshold");
+                    if ui
+                        .add(egui::Slider::new(&mut state.compare.threshold, 0..=254))
+                        .on_hover_text("Channel differences up to this are ignored")
+                        .changed()
+                    {
+                        state.compare.invalidate_overlay();
+                    }
+                    if let Some(count) = state.compare.changed_pixels {
+                        let total = state.image_dimension.0 as f32 * state.image_dimension.1 as f32;
+                        ui.label(format!(
+                            "{count} pixels differ ({:.2}%)",
+                            count as f32 / total.max(1.0) * 100.0
+                        ));
+                    }
+                }
+                _ => (),
+            }
+
+            if let (Some(current), Some(other)) =
+                (state.current_path.clone(), state.compare.path.clone())
+            {
+                if ui
+                    .button("⇄ Swap")
+                    .on_hover_text("Show the second image first")
+                    .clicked()
+                {
+                    state.compare.load(&current);
+                    state.is_loaded = false;
+                    state.player.load(&other, state.message_channel.0.clone());
+                    state.current_path = Some(other);
+                }
+            }
+            if ui.button("Stop comparing").clicked() {
+                toggle_compare(state);
+            }
+        });
+    });
+}
+
+/// Run a file action on the current image. Afterwards the next image is shown,
+/// unless the image was only renamed.
+pub fn run_file_action<F>(state: &mut OculanteState, action: F)
+where
+    F: FnOnce(&Path) -> Result<FileAction>,
+{
+    let current = match &state.current_path {
+        Some(p) if p.is_file() => p.clone(),
+        _ => return,
+    };
+    // find the next image while the current one is still listed
+    let mut next = img_shift(
+        &current,
+        1,
+        &mut state.folder_listing,
+        &state.persistent_settings,
+    );
+    if next == current {
+        next = img_shift(
+            &current,
+            -1,
+            &mut state.folder_listing,
+            &state.persistent_settings,
+        );
+    }
+
+    match action(&current) {
+        Ok(done) => {
+            info!("{done}");
+            state.message = Some(done.to_string());
+            state.folder_listing.invalidate();
+            state.thumbnail_grid.thumbnails.remove(&current);
+            if let FileAction::Rename { to, .. } = &done {
+                state.current_path = Some(to.clone());
+            } else if next != current {
+                state.is_loaded = false;
+                state.player.load(&next, state.message_channel.0.clone());
+                state.current_path = Some(next);
+            } else if !current.exists() {
+                // that was the last image
+                state.player.stop();
+                state.current_path = None;
+                state.current_image = None;
+                state.current_texture = None;
+                state.is_loaded = false;
+            }
+            state.last_file_action = Some(done);
+        }
+        Err(e) => {
+            error!("{e}");
+            state.message = Some(e.to_string());
+        }
+    }
+}
+
+/// Revert the last file action and show the image it affected
+pub fn undo_file_action(state: &mut OculanteState) {
+    let action = match state.last_file_action.take() {
+        Some(action) => action,
+        None => {
+            state.message = Some("Nothing to undo".into());
+            return;
+        }
+    };
+    match file_actions::undo(&action) {
+        Ok(path) => {
+            state.message = Some(format!("Undone: {action}"));
+            state.folder_listing.invalidate();
+            state.is_loaded = false;
+            state.player.load(&path, state.message_channel.0.clone());
+            state.current_path = Some(path);
+        }
+        Err(e) => {
+            error!("{e}");
+            state.message = Some(e.to_string());
+            state.last_file_action = Some(action);
+        }
+    }
+}
+
+/// Dialog asking for a new name of the current image
+pub fn rename_ui(ctx: &Context, state: &mut OculanteState) {
+    let mut name = match state.rename_text.take() {
+        Some(name) => name,
+        None => return,
+    };
+    let mut open = true;
+    let mut apply = false;
+    egui::Window::new("Rename")
+        .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            let response = ui.text_edit_singleline(&mut name);
+            response.request_focus();
+            if ui.input().key_pressed(Key::Enter) {
+                apply = true;
+            }
+            if ui.input().key_pressed(Key::Escape) {
+                open = false;
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Rename").clicked() {
+                    apply = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    open = false;
+                }
+            });
+        });
+
+    if apply {
+        run_file_action(state, |p| file_actions::rename(p, &name));
+    } else if open {
+        state.rename_text = Some(name);
+    }
+}
+
+/// Store new rating, label and tags of the current image in its sidecar
+fn set_xmp(state: &mut OculanteState, data: XmpData) -> bool {
+    let path = match &state.current_path {
+        Some(p) if p.is_file() => p.clone(),
+        _ => return false,
+    };
+    match xmp::write(&path, &data) {
+        Ok(_) => {
+            state.xmp = data;
+            // the image may have entered or left a rating or tag filter
+            state.folder_listing.invalidate();
+            true
+        }
+        Err(e) => {
+            error!("Can't write sidecar of {}: {e}", path.display());
+            state.message = Some(format!("Can't save metadata: {e}"));
+            false
+        }
+    }
+}
+
+pub fn rate(state: &mut OculanteState, rating: u8) {
+    let mut data = state.xmp.clone();
+    data.rating = rating as i8;
+    if set_xmp(state, data) {
+        state.message = Some(if rating == 0 {
+            "Rating removed".into()
+        } else {
+            "★".repeat(rating as usize)
+        });
+    }
+}
+
+/// Set a color label, or remove it if the image already has it
+pub fn toggle_label(state: &mut OculanteState, label: ColorLabel) {
+    let mut data = state.xmp.clone();
+    data.label = if data.label == Some(label) {
+        None
+    } else {
+        Some(label)
+    };
+    set_xmp(state, data);
+}
+
+fn label_color(label: ColorLabel) -> Color32 {
+    let [r, g, b] = label.rgb();
+    Color32::from_rgb(r, g, b)
+}
+
+/// Stars, color label and tags of the current image for the top bar
+pub fn xmp_ui(state: &mut OculanteState, ui: &mut Ui) {
+    // rejected images show no stars
+    let rating = state.xmp.rating.max(0) as u8;
+    for stars in 1..=5 {
+        let star = if stars <= rating { "★" } else { "☆" };
+        if tooltip(
+            unframed_button_colored(star, stars <= rating, ui),
+            &format!("Rate {stars} stars"),
+            &lookup(
+                &state.persistent_settings.shortcuts,
+                &shortcuts::RATE[stars as usize],
+            ),
+            ui,
+        )
+        .clicked()
+        {
+            // clicking the current rating removes it
+            rate(state, if stars == rating { 0 } else { stars });
+        }
+    }
+
+    let label_text = match state.xmp.label {
+        Some(label) => RichText::new("⏺").color(label_color(label)),
+        None => RichText::new("○"),
+    };
+    ui.menu_button(label_text, |ui| {
+        for (label, event) in ColorLabel::iter().zip(shortcuts::LABEL.iter()) {
+            let text = RichText::new(format!("⏺ {label}")).color(label_color(label));
+            if tooltip(
+                ui.selectable_label(state.xmp.label == Some(label), text),
+                "Toggle label",
+                &lookup(&state.persistent_settings.shortcuts, event),
+                ui,
+            )
+            .clicked()
+            {
+                toggle_label(state, label);
+                ui.close_menu();
+            }
+        }
+    });
+
+    let tags = if state.xmp.tags.is_empty() {
+        "Edit tags".to_string()
+    } else {
+        state.xmp.tags.join(", ")
+    };
+    if tooltip(
+        unframed_button_colored("🏷", !state.xmp.tags.is_empty(), ui),
+        &tags,
+        &lookup(&state.persistent_settings.shortcuts, &InputEvent::EditTags),
+        ui,
+    )
+    .clicked()
+    {
+        state.tag_text = Some(String::new());
+    }
+}
+
+/// Window listing the tags of the current image, with a field to add new ones
+pub fn tags_ui(ctx: &Context, state: &mut OculanteState) {
+    let mut text = match state.tag_text.take() {
+        Some(text) => text,
+        None => return,
+    };
+    let mut open = true;
+    let mut data = state.xmp.clone();
+    egui::Window::new("Tags")
+        .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            data.tags.retain(|tag| {
+                ui.horizontal(|ui| {
+                    ui.label(tag);
+                    !unframed_button("❌", ui)
+                        .on_hover_text("Remove tag")
+                        .clicked()
+                })
+                .inner
+            });
+            ui.horizontal(|ui| {
+                let response = ui.text_edit_singleline(&mut text);
+                response.request_focus();
+                let add = ui.button("Add").clicked() || ui.input().key_pressed(Key::Enter);
+                let tag = text.trim().to_string();
+                if add && !tag.is_empty() && !data.tags.contains(&tag) {
+                    data.tags.push(tag);
+                    text.clear();
+                }
+            });
+            if ui.input().key_pressed(Key::Escape) || ui.button("Close").clicked() {
+                open = false;
+            }
+        });
+
+    if data != state.xmp {
+        set_xmp(state, data);
+    }
+    if open {
+        state.tag_text = Some(text);
+    }
+}
+
+/// Start measuring lines, or stop measuring
+pub fn toggle_measure(state: &mut OculanteState) {
+    state.measure.mode = match state.measure.mode {
+        MeasureMode::Off => MeasureMode::Line,
+        _ => MeasureMode::Off,
+    };
+    state.measure.clear();
+}
+
+/// The kind of measurement and its values, while measuring
+pub fn measure_ui(ctx: &Context, state: &mut OculanteState) {
+    if !state.measure.is_active() {
+        return;
+    }
+    egui::Window::new("📏 Measure")
+        .anchor(Align2::RIGHT_TOP, [-10.0, 40.0])
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                for mode in [MeasureMode::Line, MeasureMode::Rectangle] {
+                    if ui
+                        .selectable_value(&mut state.measure.mode, mode, mode.to_string())
+                        .changed()
+                    {
+                        state.measure.clear();
+                    }
+                }
+            });
+            let rows = state.measure.rows();
+            if rows.is_empty() {
+                ui.label("Drag on the image to measure");
+            }
+            egui::Grid::new("measure").show(ui, |ui| {
+                for (label, value) in rows {
+                    ui.label(label);
+                    ui.label(RichText::new(value).monospace());
+                    ui.end_row();
+                }
+            });
+            if state.measure.dpi.is_none() {
+                ui.label("No resolution in the file, physical size unknown")
+                    .on_hover_text("Read from the EXIF resolution or the pHYs chunk of png files");
+            }
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(state.measure.start.is_some(), egui::Button::new("📋 Copy"))
+                    .on_hover_text("Copy the values as text")
+                    .clicked()
+                {
+                    ui.output().copied_text = state.measure.text();
+                }
+                if ui.button("Close").clicked() {
+                    toggle_measure(state);
+                }
+            });
+        });
+}
+
+/// Shorten a file name to fit below a thumbnail
+fn shorten(name: &str, max_chars: usize) -> String {
+    if name.chars().count() <= max_chars {
+        name.to_string()
+    } else {
+        let start = name.chars().take(max_chars - 1).collect::<String>();
+        format!("{start}…")
+    }
+}
+
+/// Thumbnails of all images in the folder of the current image
+pub fn thumbnail_grid_ui(ctx: &Context, state: &mut OculanteState, gfx: &mut Graphics) {
+    let files = grid_files(state);
+    let folder = state
+        .current_path
+        .as_ref()
+        .and_then(|p| p.parent())
+        .map(|p| p.to_path_buf());
+    if folder != state.thumbnail_grid.folder {
+        state.thumbnail_grid.thumbnails.clear();
+        state.thumbnail_grid.folder = folder;
+    }
+    state.thumbnail_grid.thumbnails.receive(gfx);
+
+    let mut open = None;
+    egui::CentralPanel::default().show(ctx, |ui| {
+        if files.is_empty() {
+            ui.heading("No images to show");
+            return;
+        }
+
+        let spacing = ui.spacing().item_spacing;
+        let cell_size = THUMB_SIZE as f32 + ui.spacing().button_padding.x * 2.;
+        let text_height = ui.text_style_height(&TextStyle::Body);
+        let row_height = cell_size + (text_height + spacing.y) * 2.;
+        let columns = ((ui.available_width() + spacing.x) / (cell_size + spacing.x))
+            .floor()
+            .max(1.) as usize;
+        let rows = (files.len() + columns - 1) / columns;
+        state.thumbnail_grid.columns = columns;
+        state.thumbnail_grid.selected = state.thumbnail_grid.selected.min(files.len() - 1);
+
+        let mut scroll_area = egui::ScrollArea::vertical().auto_shrink([false, false]);
+        if state.thumbnail_grid.scroll_to_selected {
+            state.thumbnail_grid.scroll_to_selected = false;
+            // only scroll if the selection left the visible rows
+            let selected_row = state.thumbnail_grid.selected / columns;
+            let visible = state.thumbnail_grid.visible_rows.clone();
+            let stride = row_height + spacing.y;
+            if selected_row <= visible.start || visible.is_empty() {
+                scroll_area = scroll_area.vertical_scroll_offset(selected_row as f32 * stride);
+            } else if selected_row + 1 >= visible.end {
+                let offset = (selected_row + 1) as f32 * stride - ui.available_height();
+                scroll_area = scroll_area.vertical_scroll_offset(offset.max(0.));
+            }
+        }
+
+        scroll_area.show_rows(ui, row_height, rows, |ui, row_range| {
+            state.thumbnail_grid.visible_rows = row_range.clone();
+            // Only visible rows are laid out, so thumbnails are requested as they scroll into view
+            for row in row_range {
+                ui.horizontal(|ui| {
+                    ui.set_height(row_height);
+                    for index in row * columns..((row + 1) * columns).min(files.len()) {
+                        let path = &files[index];
+                        let selected = index == state.thumbnail_grid.selected;
+                        let name = path
+                            .file_name()
+                            .map(|n| n.to_string_lossy().to_string())
+                            .unwrap_or_default();
+
+                        ui.vertical(|ui| {
+                            ui.set_width(cell_size);
+                            let cell = Vec2::new(cell_size, cell_size);
+                            let (response, details) = match state
+                                .thumbnail_grid
+                                .thumbnails
+                                .get(path)
+                            {
+                                Thumbnail::Ready(texture, info) => {
+                                    let size = Vec2::new(texture.width(), texture.height());
+                                    let tex_id = gfx.egui_register_texture(texture);
+                                    (
+                                        ui.add_sized(
+                                            cell,
+                                            egui::ImageButton::new(tex_id, size).selected(selected),
+                                        ),
+                                        match info.dimensions {
+                                            Some((width, height)) => format!(
+                                                "{width}x{height}, {}",
+                                                format_size(info.file_size)
+                                            ),
+                                            None => format_size(info.file_size),
+                                        },
+                                    )
+                                }
+                                Thumbnail::Loading => (
+                                    ui.add_sized(cell, egui::SelectableLabel::new(selected, "⏳")),
+                                    "…".to_string(),
+                                ),
+                                Thumbnail::Failed => (
+                                    ui.add_sized(cell, egui::SelectableLabel::new(selected, "⚠")),
+                                    "Can't load".to_string(),
+                                ),
+                            };
+                            if response.on_hover_text(&name).clicked() {
+                                open = Some(path.clone());
+                            }
+                            ui.add(egui::Label::new(shorten(&name, 24)).wrap(false));
+                            ui.add(egui::Label::new(RichText::new(details).weak()).wrap(false));
+                        });
+                    }
+                });
+            }
+        });
+    });
+
+    if let Some(path) = open {
+        open_from_grid(state, path);
+    }
+}
+
+// TODO redo as impl UI
+pub fn tooltip(r: Response, tooltip: &str, hotkey: &str, _ui: &mut Ui) -> Response {
+    r.on_hover_ui(|ui| {
+        let avg = (ui.style().visuals.selection.bg_fill.r() as i32
+            + ui.style().visuals.selection.bg_fill.g() as i32
+            + ui.style().visuals.selection.bg_fill.b() as i32)
+            / 3;
+        let contrast_color: u8 = if avg > 128 { 0 } else { 255 };
+        ui.horizontal(|ui| {
+            ui.label(tooltip);
+            ui.label(
+                RichText::new(hotkey)
+                    .monospace()
+                    .color(Color32::from_gray(contrast_color))
+                    .background_color(ui.style().visuals.selection.bg_fill),
+            );
+        });
+    })
+}
+
+// TODO redo as impl UI
+pub fn unframed_button(text: impl Into<WidgetText>, ui: &mut Ui) -> Response {
+    ui.add(egui::Button::new(text).frame(false))
+}
+
+pub fn unframed_button_colored(text: impl Into<String>, is_colored: bool, ui: &mut Ui) -> Response {
+    if is_colored {
+        ui.add(
+            egui::Button::new(RichText::new(text).color(ui.style().visuals.selection.bg_fill))
+                .frame(false),
+        )
+    } else {
+        ui.add(egui::Button::new(RichText::new(text)).frame(false))
+    }
+}
+
+pub fn stroke_ui(
+    stroke: &mut PaintStroke,
+    brushes: &Vec<RgbaImage>,
+    ui: &mut Ui,
+    gfx: &mut Graphics,
+) -> Response {
+    let mut combined_response = ui.color_edit_button_rgba_unmultiplied(&mut stroke.color);
+
+    let r = ui
+        .checkbox(&mut stroke.fade, "")
+        .on_hover_text("Fade out the stroke over its path");
+    if r.changed() {
+        combined_response.changed = true;
+    }
+    if r.hovered() {
+        combined_response.hovered = true;
+    }
+
+    let r = ui
+        .checkbox(&mut stroke.flip_random, "")
+        .on_hover_text("Flip brush in X any Y randomly to make stroke less uniform");
+    if r.changed() {
+        combined_response.changed = true;
+    }
+    if r.hovered() {
         combined_response.hovered = true;
     }
 
@@ -1321,6 +2965,161 @@ fn jpg_lossless_ui(state: &mut OculanteState, ui: &mut Ui) {
     }
 }
 
+/// A ui to export an svg rendered at a multiple of its document size
+fn svg_export_ui(state: &mut OculanteState, ui: &mut Ui) {
+    if let Some(p) = &state.current_path.clone() {
+        if !is_svg(p) {
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Rasterise at");
+            ui.add(
+                egui::DragValue::new(&mut state.svg_export_scale)
+                    .clamp_range(1..=16)
+                    .suffix("×"),
+            );
+            if ui
+                .button("💾 Export")
+                .on_hover_text("Render the svg at this multiple of its size and save it next to the original, using the selected file type.")
+                .clicked()
+            {
+                let scale = state.svg_export_scale;
+                let stem = p.file_stem().unwrap_or_default().to_string_lossy();
+                let target = p.with_file_name(format!(
+                    "{stem}_{scale}x.{}",
+                    state.edit_state.export_extension
+                ));
+                let result = load_svg(p)
+                    .and_then(|tree| render_svg(&tree, scale as f32))
+                    .and_then(|img| save_image(&img, &target));
+                state.message = Some(match result {
+                    Ok(_) => format!("Saved {}", target.display()),
+                    Err(e) => e.to_string(),
+                });
+            }
+        });
+    }
+}
+
+fn animation_export_ui(state: &mut OculanteState, ui: &mut Ui) {
+    let status = match state.player.status() {
+        Some(status) => status,
+        None => return,
+    };
+    let p = match &state.current_path.clone() {
+        Some(p) => p.clone(),
+        None => return,
+    };
+    let stem = p
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+
+    ui.collapsing("Animation export", |ui| {
+        ui.checkbox(
+            &mut state.animation_export_edits,
+            "Apply edits to all frames",
+        );
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("anim_ext")
+                .selected_text(&state.animation_export_format)
+                .show_ui(ui, |ui| {
+                    for f in ANIMATION_FORMATS {
+                        ui.selectable_value(&mut state.animation_export_format, f.to_string(), f);
+                    }
+                });
+
+            if ui
+                .button("💾 Export animation")
+                .on_hover_text("Save all frames with their timing next to the original.")
+                .clicked()
+            {
+                let format = state.animation_export_format.clone();
+                // apng files commonly use the png extension
+                let ext = if format == "apng" { "png" } else { &format };
+                let target = p.with_file_name(format!("{stem}_export.{ext}"));
+                export_frames_threaded(state, &p, move |frames| {
+                    export_animation(&frames, &target, &format)
+                        .map(|_| format!("Saved {}", target.display()))
+                });
+            }
+        });
+
+        ui.horizontal(|ui| {
+            if ui
+                .button("💾 Export frames")
+                .on_hover_text(
+                    "Save every frame as a numbered png into a folder next to the original.",
+                )
+                .clicked()
+            {
+                let dir = p.with_file_name(format!("{stem}_frames"));
+                let stem = stem.clone();
+                export_frames_threaded(state, &p, move |frames| {
+                    export_sequence(&frames, &dir, &stem)
+                        .map(|files| format!("Saved {} frames to {}", files.len(), dir.display()))
+                });
+            }
+
+            if ui
+                .button("💾 Export current frame")
+                .on_hover_text("Save the frame currently shown, using the selected file type.")
+                .clicked()
+            {
+                if let Some(img) = &state.current_image {
+                    let target = p.with_file_name(format!(
+                        "{stem}_{}.{}",
+                        status.index, state.edit_state.export_extension
+                    ));
+                    let result = if state.animation_export_edits {
+                        save_image(&state.edit_state.apply(img), &target)
+                    } else {
+                        save_image(img, &target)
+                    };
+                    state.message = Some(match result {
+                        Ok(_) => format!("Saved {}", target.display()),
+                        Err(e) => e.to_string(),
+                    });
+                }
+            }
+        });
+    });
+}
+
+/// Decode all frames of the playing sequence or of `path` again in a thread, optionally run
+/// the edit operators on them, and pass them to `export`. The result is reported as a message.
+fn export_frames_threaded<F>(state: &mut OculanteState, path: &Path, export: F)
+where
+    F: FnOnce(Vec<Frame>) -> Result<String> + Send + 'static,
+{
+    let path = path.to_path_buf();
+    let sequence = state.sequence.clone();
+    let fps = state.sequence_fps;
+    let edit_state = state
+        .animation_export_edits
+        .then(|| state.edit_state.clone());
+    let message_sender = state.message_channel.0.clone();
+    state.message = Some("Exporting...".into());
+
+    std::thread::spawn(move || {
+        let result = animation_frames(&path, sequence.as_ref(), fps).and_then(|mut frames| {
+            if let Some(edit_state) = &edit_state {
+                for frame in &mut frames {
+                    frame.buffer = edit_state.apply(&frame.buffer);
+                }
+            }
+            export(frames)
+        });
+        _ = message_sender.send(match result {
+            Ok(message) => message,
+            Err(e) => e.to_string(),
+        });
+    });
+}
+
 fn keybinding_ui(app: &mut App, state: &mut OculanteState, ui: &mut Ui) {
     // Make sure no shortcuts are received by the application
     state.key_grab = true;
@@ -6,18 +6,18 @@ use image::{EncodableLayout, RgbaImage};
 use log::{debug, error, info};
 use nalgebra::{clamp, Vector2};
 use notan::graphics::Texture;
-use notan::prelude::Graphics;
+use notan::prelude::{Graphics, TextureFilter};
 use notan::AppState;
 
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 use rayon::slice::ParallelSliceMut;
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::ffi::OsStr;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::{Path, PathBuf};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use exr::prelude as exrs;
 use exr::prelude::*;
@@ -28,14 +28,30 @@ use image::{self};
 use libwebp_sys::{WebPDecodeRGBA, WebPGetInfo};
 use psd::Psd;
 use rgb::*;
+use serde::{Deserialize, Serialize};
 use std::io::Read;
 use std::sync::mpsc;
-use std::sync::mpsc::{Receiver, Sender};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
 use strum::Display;
 use strum_macros::EnumIter;
 
+use crate::background::Checkerboard;
+use crate::compare::{BeforeAfter, Compare};
+use crate::file_actions::FileAction;
+use crate::gif_stream::{is_gif, GifStream};
 use crate::image_editing::EditState;
+use crate::ktx::open_ktx;
+use crate::measure::Measure;
+use crate::navigation::FolderListing;
+use crate::orientation::Orientation;
+use crate::sequence::{frame_delay, FrameCache, ImageSequence};
 use crate::settings::PersistentSettings;
+use crate::slideshow::Slideshow;
+use crate::svg::{load_svg, render_svg, SvgRenderer};
+use crate::thumbnails::ThumbnailGrid;
+use crate::watch::{FolderWatcher, WatchFolder};
+use crate::xmp::XmpData;
 
 fn is_pixel_fully_transparent(p: &Rgba<u8>) -> bool {
     p.0 == [0, 0, 0, 0]
@@ -129,22 +145,68 @@ impl ExtendedImageInfo {
     }
 }
 
+/// Commands understood by a running animation player
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlayerCommand {
+    Stop,
+    Pause,
+    Resume,
+    /// Pause and move the given number of frames forwards or backwards
+    Step(isize),
+    /// Show a specific frame
+    Seek(usize),
+    /// Playback speed multiplier
+    Speed(f32),
+}
+
+/// What the player thread is currently showing, used by the UI
+#[derive(Debug, Clone)]
+pub struct PlaybackStatus {
+    pub index: usize,
+    /// Delay of every frame in milliseconds
+    pub delays: Vec<u16>,
+    pub paused: bool,
+    pub speed: f32,
+    /// How often the animation played through
+    pub loops: u32,
+}
+
+impl Default for PlaybackStatus {
+    fn default() -> Self {
+        Self {
+            index: 0,
+            delays: vec![],
+            paused: false,
+            speed: 1.0,
+            loops: 0,
+        }
+    }
+}
+
+impl PlaybackStatus {
+    pub fn frame_count(&self) -> usize {
+        self.delays.len()
+    }
+}
+
 #[derive(Debug)]
 pub struct Player {
     pub frame_sender: Sender<FrameCollection>,
     pub image_sender: Sender<Frame>,
-    pub stop_sender: Sender<()>,
+    pub command_sender: Sender<PlayerCommand>,
+    pub playback: Arc<Mutex<PlaybackStatus>>,
 }
 
 impl Player {
     pub fn new(image_sender: Sender<Frame>) -> Player {
         let (frame_sender, _): (Sender<FrameCollection>, Receiver<FrameCollection>) =
             mpsc::channel();
-        let (stop_sender, _): (Sender<()>, Receiver<()>) = mpsc::channel();
+        let (command_sender, _): (Sender<PlayerCommand>, Receiver<PlayerCommand>) = mpsc::channel();
         Player {
             frame_sender,
             image_sender,
-            stop_sender,
+            command_sender,
+            playback: Default::default(),
         }
     }
 
@@ -155,18 +217,56 @@ impl Player {
 
     pub fn load(&mut self, img_location: &PathBuf, message_sender: Sender<String>) {
         self.stop();
-        let (stop_sender, stop_receiver): (Sender<()>, Receiver<()>) = mpsc::channel();
-        self.stop_sender = stop_sender;
+        let (command_sender, command_receiver): (Sender<PlayerCommand>, Receiver<PlayerCommand>) =
+            mpsc::channel();
+        self.command_sender = command_sender;
+        // A fresh status, so a finishing thread can't overwrite the new one
+        self.playback = Default::default();
         send_image_threaded(
             &img_location,
             self.image_sender.clone(),
             message_sender,
-            stop_receiver,
+            command_receiver,
+            self.playback.clone(),
+        );
+    }
+
+    /// Play a list of files as animation at the given frames per second
+    pub fn load_sequence(&mut self, files: Vec<PathBuf>, fps: f32, message_sender: Sender<String>) {
+        self.stop();
+        let (command_sender, command_receiver): (Sender<PlayerCommand>, Receiver<PlayerCommand>) =
+            mpsc::channel();
+        self.command_sender = command_sender;
+        self.playback = Default::default();
+        send_sequence_threaded(
+            files,
+            fps,
+            self.image_sender.clone(),
+            message_sender,
+            command_receiver,
+            self.playback.clone(),
         );
     }
 
     pub fn stop(&self) {
-        _ = self.stop_sender.send(());
+        _ = self.command_sender.send(PlayerCommand::Stop);
+        if let Ok(mut playback) = self.playback.lock() {
+            *playback = Default::default();
+        }
+    }
+
+    /// Send a command to the animation thread, if there is one
+    pub fn command(&self, command: PlayerCommand) {
+        _ = self.command_sender.send(command);
+    }
+
+    /// A copy of the playback status. Returns `None` if no animation is playing.
+    pub fn status(&self) -> Option<PlaybackStatus> {
+        self.playback
+            .lock()
+            .ok()
+            .filter(|p| p.frame_count() > 1)
+            .map(|p| p.clone())
     }
 }
 
@@ -174,41 +274,36 @@ pub fn send_image_threaded(
     img_location: &PathBuf,
     texture_sender: Sender<Frame>,
     message_sender: Sender<String>,
-    stop_receiver: Receiver<()>,
+    command_receiver: Receiver<PlayerCommand>,
+    playback: Arc<Mutex<PlaybackStatus>>,
 ) {
     let loc = img_location.clone();
 
     thread::spawn(move || {
+        if is_gif(&loc) {
+            // gifs are streamed, so long animations show up without decoding everything first
+            match GifStream::new(&loc) {
+                Ok(mut stream) => {
+                    if stream.has_frame(1) {
+                        play_animation(stream, texture_sender, command_receiver, playback);
+                    } else if let Some(frame) = stream.frame(0) {
+                        let _ = texture_sender.send(Frame::new_still(frame.buffer));
+                    } else {
+                        _ = message_sender.send("Gif contains no frames".into());
+                    }
+                }
+                Err(e) => {
+                    error!("{e}");
+                    _ = message_sender.send(e.to_string());
+                }
+            }
+            return;
+        }
+
         match open_image(&loc) {
             Ok(col) => {
-                let cycles = if col.repeat { 200 } else { 1 };
-
                 if col.repeat && col.frames.len() > 1 {
-                    let mut i = 0;
-
-                    // Send reset frame
-                    if let Some(f) = col.frames.first() {
-                        _ = texture_sender
-                            .clone()
-                            .send(Frame::new_reset(f.buffer.clone()));
-                    }
-
-                    while i < cycles {
-                        // let frames = col.frames.clone();
-                        for frame in &col.frames {
-                            if stop_receiver.try_recv().is_ok() {
-                                info!("Stopped from receiver.");
-                                return;
-                            }
-                            let _ = texture_sender.send(frame.clone());
-                            if frame.delay > 0 {
-                                thread::sleep(Duration::from_millis(frame.delay as u64));
-                            } else {
-                                thread::sleep(Duration::from_millis(40 as u64));
-                            }
-                        }
-                        i += 1;
-                    }
+                    play_animation(col, texture_sender, command_receiver, playback);
                 } else {
                     // single frame. This saves one clone().
                     for frame in col.frames {
@@ -224,6 +319,237 @@ pub fn send_image_threaded(
     });
 }
 
+/// Play a numbered image sequence, decoding frames as they are needed
+pub fn send_sequence_threaded(
+    files: Vec<PathBuf>,
+    fps: f32,
+    texture_sender: Sender<Frame>,
+    message_sender: Sender<String>,
+    command_receiver: Receiver<PlayerCommand>,
+    playback: Arc<Mutex<PlaybackStatus>>,
+) {
+    thread::spawn(move || {
+        play_animation(
+            FrameCache::new(files, frame_delay(fps), message_sender),
+            texture_sender,
+            command_receiver,
+            playback,
+        );
+    });
+}
+
+/// Supplies frames to the animation player
+pub trait AnimationSource {
+    /// The frame at `index`, or `None` if it can't be supplied
+    fn frame(&mut self, index: usize) -> Option<Frame>;
+
+    /// The number of frames known so far
+    fn frame_count(&self) -> usize;
+
+    /// How long to show a frame, in milliseconds
+    fn delay(&self, index: usize) -> u16;
+
+    /// Whether there is a frame at `index`. Streaming sources may need to decode to find out.
+    fn has_frame(&mut self, index: usize) -> bool {
+        index < self.frame_count()
+    }
+
+    /// Whether all frames are known
+    fn complete(&self) -> bool {
+        true
+    }
+
+    /// How often the animation is played, `None` meaning forever
+    fn plays(&self) -> Option<u32> {
+        None
+    }
+}
+
+impl AnimationSource for FrameCollection {
+    fn frame(&mut self, index: usize) -> Option<Frame> {
+        self.frames.get(index).cloned()
+    }
+
+    fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    fn delay(&self, index: usize) -> u16 {
+        self.frames.get(index).map(|f| f.delay).unwrap_or_default()
+    }
+}
+
+/// Keeps decoded frames by index, dropping the oldest ones once a memory budget is reached
+#[derive(Debug)]
+pub struct FrameStore {
+    frames: HashMap<usize, Frame>,
+    /// Stored frame indices, oldest first
+    order: VecDeque<usize>,
+    bytes: usize,
+    max_bytes: usize,
+}
+
+impl FrameStore {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            frames: Default::default(),
+            order: Default::default(),
+            bytes: 0,
+            max_bytes,
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<Frame> {
+        self.frames.get(&index).cloned()
+    }
+
+    pub fn insert(&mut self, index: usize, frame: Frame) {
+        if let Some(replaced) = self.frames.remove(&index) {
+            self.bytes -= replaced.buffer.as_raw().len();
+            self.order.retain(|i| *i != index);
+        }
+        let size = frame.buffer.as_raw().len();
+        while self.bytes + size > self.max_bytes {
+            match self.order.pop_front() {
+                Some(oldest) => {
+                    if let Some(f) = self.frames.remove(&oldest) {
+                        self.bytes -= f.buffer.as_raw().len();
+                    }
+                }
+                None => break,
+            }
+        }
+        self.bytes += size;
+        self.order.push_back(index);
+        self.frames.insert(index, frame);
+    }
+}
+
+/// Loop through frames and react to player commands until stopped.
+/// Frames the source can't supply are skipped.
+fn play_animation<S: AnimationSource>(
+    mut source: S,
+    texture_sender: Sender<Frame>,
+    command_receiver: Receiver<PlayerCommand>,
+    playback: Arc<Mutex<PlaybackStatus>>,
+) {
+    // Send reset frame
+    match source.frame(0) {
+        Some(f) => _ = texture_sender.send(Frame::new_reset(f.buffer)),
+        None => return,
+    }
+
+    let mut index = 0;
+    let mut cycle = 0;
+    let mut paused = false;
+    let mut finished = false;
+    let mut speed = 1.0_f32;
+    let mut show = true;
+
+    loop {
+        if show {
+            if let Some(frame) = source.frame(index) {
+                let _ = texture_sender.send(frame);
+            }
+            if let Ok(mut p) = playback.lock() {
+                // streaming sources learn about more frames while playing
+                if p.delays.len() != source.frame_count() {
+                    p.delays = (0..source.frame_count()).map(|i| source.delay(i)).collect();
+                }
+                p.index = index;
+                p.paused = paused;
+                p.speed = speed;
+            }
+        }
+
+        let delay = match source.delay(index) {
+            0 => 40,
+            delay => delay,
+        };
+        let mut deadline = Instant::now() + Duration::from_secs_f32(delay as f32 / 1000. / speed);
+
+        // Wait for the next frame to be due, or for a command
+        let advance = loop {
+            let command = if paused || finished {
+                match command_receiver.recv() {
+                    Ok(c) => c,
+                    Err(_) => return,
+                }
+            } else {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                match command_receiver.recv_timeout(timeout) {
+                    Ok(c) => c,
+                    Err(RecvTimeoutError::Timeout) => break true,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            };
+
+            match command {
+                PlayerCommand::Stop => {
+                    info!("Stopped from receiver.");
+                    return;
+                }
+                PlayerCommand::Pause => paused = true,
+                PlayerCommand::Resume => {
+                    paused = false;
+                    if finished {
+                        finished = false;
+                        cycle = 0;
+                    }
+                    deadline =
+                        Instant::now() + Duration::from_secs_f32(delay as f32 / 1000. / speed);
+                }
+                PlayerCommand::Step(n) => {
+                    paused = true;
+                    let target = index as isize + n;
+                    index = if target >= 0 && source.has_frame(target as usize) {
+                        target as usize
+                    } else {
+                        target.rem_euclid(source.frame_count() as isize) as usize
+                    };
+                    break false;
+                }
+                PlayerCommand::Seek(i) => {
+                    index = i.min(source.frame_count() - 1);
+                    break false;
+                }
+                PlayerCommand::Speed(s) => speed = s.max(0.01),
+            }
+            if let Ok(mut p) = playback.lock() {
+                p.paused = paused;
+                p.speed = speed;
+            }
+        };
+
+        show = true;
+        if advance {
+            if source.has_frame(index + 1) {
+                index += 1;
+            } else {
+                cycle += 1;
+                if let Ok(mut p) = playback.lock() {
+                    p.loops += 1;
+                }
+                if source
+                    .plays()
+                    .map(|plays| cycle >= plays)
+                    .unwrap_or_default()
+                {
+                    // Keep the last frame and wait for commands
+                    finished = true;
+                    paused = true;
+                    show = false;
+                    if let Ok(mut p) = playback.lock() {
+                        p.paused = true;
+                    }
+                } else {
+                    index = 0;
+                }
+            }
+        }
+    }
+}
+
 pub fn send_image_blocking(
     img_location: &PathBuf,
     texture_sender: Sender<Frame>,
@@ -256,6 +582,8 @@ pub enum FrameSource {
     Still,
     EditResult,
     Reset,
+    /// The image at this path rendered again at a different resolution
+    Rerender(PathBuf),
 }
 
 /// A single frame
@@ -268,7 +596,7 @@ pub struct Frame {
 }
 
 impl Frame {
-    fn new(buffer: RgbaImage, delay: u16, source: FrameSource) -> Frame {
+    pub fn new(buffer: RgbaImage, delay: u16, source: FrameSource) -> Frame {
         Frame {
             buffer,
             delay,
@@ -276,7 +604,7 @@ impl Frame {
         }
     }
 
-    fn new_reset(buffer: RgbaImage) -> Frame {
+    pub fn new_reset(buffer: RgbaImage) -> Frame {
         Frame {
             buffer,
             delay: 0,
@@ -293,6 +621,14 @@ impl Frame {
         }
     }
 
+    pub fn new_rerender(buffer: RgbaImage, path: &Path) -> Frame {
+        Frame {
+            buffer,
+            delay: 0,
+            source: FrameSource::Rerender(path.to_path_buf()),
+        }
+    }
+
     pub fn new_still(buffer: RgbaImage) -> Frame {
         Frame {
             buffer,
@@ -318,7 +654,7 @@ impl FrameCollection {
     }
 }
 
-#[derive(Debug, PartialEq, EnumIter, Display, Clone, Copy)]
+#[derive(Debug, PartialEq, EnumIter, Display, Clone, Copy, Serialize, Deserialize)]
 pub enum Channel {
     Red,
     Green,
@@ -380,6 +716,52 @@ pub struct OculanteState {
     pub network_mode: bool,
     pub toast_cooldown: f32,
     pub fullscreen_offset: Option<(i32, i32)>,
+    /// The mip level displayed for textures with mip maps
+    pub mip_level: u32,
+    pub mip_count: u32,
+    /// Renders the current svg again when zooming
+    pub svg_renderer: Option<SvgRenderer>,
+    /// Scale factor for exporting a rasterised svg
+    pub svg_export_scale: u32,
+    pub animation_export_format: String,
+    /// Run the edit operators on every frame when exporting animations
+    pub animation_export_edits: bool,
+    /// The numbered image sequence the current file belongs to
+    pub sequence: Option<ImageSequence>,
+    /// Frames per second to play image sequences at
+    pub sequence_fps: f32,
+    /// Sorted images of the current folder
+    pub folder_listing: FolderListing,
+    pub slideshow: Slideshow,
+    pub thumbnail_grid: ThumbnailGrid,
+    /// Watches the folder of the current image for changes
+    pub folder_watcher: FolderWatcher,
+    /// When the last event came in, to slow down drawing while idle
+    pub last_input: Instant,
+    /// A reload of this file is on its way, which keeps the zoom and offset
+    pub keep_view_for: Option<PathBuf>,
+    /// Shows new images of a folder as they arrive
+    pub watch_folder: WatchFolder,
+    /// The last file operation, which can be undone
+    pub last_file_action: Option<FileAction>,
+    /// The new name while the rename dialog is open
+    pub rename_text: Option<String>,
+    /// Rating, label and tags of the current image
+    pub xmp: XmpData,
+    /// The tag being typed while the tag editor is open
+    pub tag_text: Option<String>,
+    /// A second image shown next to the current one
+    pub compare: Compare,
+    /// The original image shown with the edit result
+    pub before_after: BeforeAfter,
+    /// Drawn behind the image when the checkerboard background is on
+    pub checkerboard: Checkerboard,
+    /// Upload the image again, as the texture filter changed
+    pub refresh_texture: bool,
+    /// View rotation and mirroring of the current image
+    pub orientation: Orientation,
+    /// Distance or area being measured on the image
+    pub measure: Measure,
 }
 
 impl Default for OculanteState {
@@ -422,6 +804,31 @@ impl Default for OculanteState {
             window_size: Default::default(),
             toast_cooldown: 0.,
             fullscreen_offset: None,
+            mip_level: 0,
+            mip_count: 1,
+            svg_renderer: None,
+            svg_export_scale: 2,
+            animation_export_format: "gif".into(),
+            animation_export_edits: true,
+            sequence: None,
+            sequence_fps: 24.,
+            folder_listing: Default::default(),
+            slideshow: Default::default(),
+            thumbnail_grid: Default::default(),
+            folder_watcher: Default::default(),
+            last_input: Instant::now(),
+            keep_view_for: None,
+            watch_folder: Default::default(),
+            last_file_action: None,
+            rename_text: None,
+            xmp: Default::default(),
+            tag_text: None,
+            compare: Default::default(),
+            before_after: Default::default(),
+            checkerboard: Default::default(),
+            refresh_texture: Default::default(),
+            orientation: Default::default(),
+            measure: Default::default(),
         }
     }
 }
@@ -462,25 +869,27 @@ pub fn disp_col_norm(col: [f32; 4], divisor: f32) -> String {
     )
 }
 
-/// Advance to the prev/next image
-pub fn img_shift(file: &PathBuf, inc: isize) -> PathBuf {
-    if let Some(parent) = file.parent() {
-        if let Ok(info) = std::fs::read_dir(parent) {
-            let mut files = info
-                .map(|x| x.unwrap().path())
-                .filter(|x| is_ext_compatible(x))
-                .collect::<Vec<PathBuf>>();
-            files.sort();
-            for (i, f) in files.iter().enumerate() {
-                if f == file {
-                    if let Some(next) = files.get((i as isize + inc) as usize) {
-                        return next.clone();
-                    }
-                }
-            }
-        }
-    }
-    file.clone()
+/// The image `inc` steps away from `file` in its folder, using the sort order and filter
+/// from the settings. Returns `file` if there is none.
+pub fn img_shift(
+    file: &PathBuf,
+    inc: isize,
+    listing: &mut FolderListing,
+    settings: &PersistentSettings,
+) -> PathBuf {
+    let files = listing.files(
+        file,
+        settings.sort_order,
+        settings.sort_descending,
+        &settings.file_filter(),
+    );
+    let next = match files.iter().position(|f| f == file) {
+        Some(i) => files.get((i as isize + inc) as usize),
+        // The current file is filtered out, so start at either end
+        None if inc > 0 => files.first(),
+        None => files.last(),
+    };
+    next.cloned().unwrap_or_else(|| file.clone())
 }
 
 pub fn is_ext_compatible(fname: &PathBuf) -> bool {
@@ -509,6 +918,9 @@ pub fn is_ext_compatible(fname: &PathBuf) -> bool {
         "pnm" => true,
         "svg" => true,
         "ff" => true,
+        "ktx" => true,
+        "ktx2" => true,
+        "qoi" => true,
         _ => false,
     }
 }
@@ -588,8 +1000,9 @@ pub fn pos_from_coord(
     pt: Vector2<f32>,
     bounds: Vector2<f32>,
     scale: f32,
+    orientation: &Orientation,
 ) -> Vector2<f32> {
-    let mut size = (pt - origin) / scale;
+    let mut size = orientation.to_image((pt - origin) / scale, bounds);
     size.x = clamp(size.x, 0.0, bounds.x - 1.0);
     size.y = clamp(size.y, 0.0, bounds.y - 1.0);
     size
@@ -639,39 +1052,26 @@ pub fn open_image(img_location: &PathBuf) -> Result<FrameCollection> {
                 col.add_still(buf);
             }
         }
-        "svg" => {
-            // TODO: Should the svg be scaled? if so by what number?
-            // This should be specified in a smarter way, maybe resolution * x?
-            //let (width, height) = (3000, 3000);
-            let opt = usvg::Options::default();
-            let svg_data = std::fs::read(&img_location)?;
-            if let Ok(rtree) = usvg::Tree::from_data(&svg_data, &opt.to_ref()) {
-                // let pixmap_size = rtree.svg_node().size.to_screen_size()
-                let pixmap_size = rtree.size.to_screen_size();
-                // .scale_to(ScreenSize::new(width, height)?)
-                // ;
-
-                if let Some(mut pixmap) =
-                    tiny_skia::Pixmap::new(pixmap_size.width(), pixmap_size.height())
-                {
-                    resvg::render(
-                        &rtree,
-                        usvg::FitTo::Original,
-                        tiny_skia::Transform::identity(),
-                        pixmap.as_mut(),
-                    )
-                    .ok_or(anyhow!("Can't render SVG"))?;
-                    // resvg::render(&rtree, usvg::FitTo::Height(height), pixmap.as_mut())?;
-                    let buf: Option<RgbaImage> = image::ImageBuffer::from_raw(
-                        pixmap_size.width(),
-                        pixmap_size.height(),
-                        pixmap.data().to_vec(),
-                    );
-                    if let Some(valid_buf) = buf {
-                        col.add_still(valid_buf);
-                    }
+        "ktx" | "ktx2" => {
+            // Start with the full resolution mip level, others can be selected in the info panel
+            col.add_still(open_ktx(&img_location, 0)?);
+        }
+        "qoi" => {
+            let contents = std::fs::read(&img_location)?;
+            let (header, decoded) = qoi::decode_to_vec(&contents)?;
+            let buf = match header.channels {
+                qoi::Channels::Rgba => RgbaImage::from_raw(header.width, header.height, decoded),
+                qoi::Channels::Rgb => {
+                    image::RgbImage::from_raw(header.width, header.height, decoded)
+                        .map(|rgb| image::DynamicImage::ImageRgb8(rgb).to_rgba8())
                 }
-            }
+            };
+            col.add_still(buf.ok_or(anyhow!("Can't create QOI ImageBuffer with given res"))?);
+        }
+        "svg" => {
+            // Render at the document size first. Once displayed, the svg is rendered
+            // again at the zoom level it is viewed at (see `SvgRenderer`).
+            col.add_still(render_svg(&load_svg(&img_location)?, 1.0)?);
         }
         "exr" => {
             let reader = exrs::read()
@@ -759,35 +1159,11 @@ pub fn open_image(img_location: &PathBuf) -> Result<FrameCollection> {
             }
         }
         "gif" => {
-            let file = File::open(&img_location)?;
-
-            // Below is a workaround for partially corrupt gifs.
-            let mut gif_opts = gif::DecodeOptions::new();
-            gif_opts.set_color_output(gif::ColorOutput::Indexed);
-            let mut decoder = gif_opts.read_info(file)?;
-            let dim = (decoder.width() as u32, decoder.height() as u32);
-            let mut screen = gif_dispose::Screen::new_decoder(&decoder);
-            loop {
-                if let Ok(i) = decoder.read_next_frame() {
-                    debug!("decoded frame");
-                    if let Some(frame) = i {
-                        screen.blit_frame(&frame)?;
-                        let buf: Option<image::RgbaImage> = image::ImageBuffer::from_raw(
-                            dim.0,
-                            dim.1,
-                            screen.pixels.buf().as_bytes().to_vec(),
-                        );
-                        col.add_anim_frame(
-                            buf.ok_or(anyhow!("Can't read gif frame"))?,
-                            frame.delay * 10,
-                        );
-                        col.repeat = true;
-                    } else {
-                        break;
-                    }
-                } else {
-                    break;
-                }
+            let mut stream = GifStream::new(img_location)?;
+            while let Some(frame) = stream.decode_next() {
+                debug!("decoded frame");
+                col.add_anim_frame(frame.buffer, frame.delay);
+                col.repeat = true;
             }
 
             // TODO: Re-enable if https://github.com/image-rs/image/issues/1818 is resolved
@@ -825,6 +1201,10 @@ pub trait ImageExt {
         unimplemented!()
     }
 
+    fn to_texture_with_filter(&self, _: &mut Graphics, _: TextureFilter) -> Option<Texture> {
+        unimplemented!()
+    }
+
     fn to_texture_premult(&self, _: &mut Graphics) -> Option<Texture> {
         unimplemented!()
     }
@@ -844,12 +1224,17 @@ impl ImageExt for RgbaImage {
     }
 
     fn to_texture(&self, gfx: &mut Graphics) -> Option<Texture> {
+        self.to_texture_with_filter(gfx, TextureFilter::Linear)
+    }
+
+    /// Upload with `mag` used when the texture is drawn larger than its size
+    fn to_texture_with_filter(&self, gfx: &mut Graphics, mag: TextureFilter) -> Option<Texture> {
         gfx.create_texture()
             .from_bytes(self, self.width() as i32, self.height() as i32)
             .with_mipmaps(true)
             .with_format(notan::prelude::TextureFormat::SRgba8)
             // .with_premultiplied_alpha()
-            // .with_filter(TextureFilter::Linear, TextureFilter::Nearest)
+            .with_filter(TextureFilter::Linear, mag)
             // .with_wrap(TextureWrap::Repeat, TextureWrap::Repeat)
             .build()
             .ok()
@@ -890,6 +1275,25 @@ impl ImageExt for (u32, u32) {
     }
 }
 
+/// Save an image, choosing the encoder from the file extension
+pub fn save_image(img: &RgbaImage, path: &Path) -> Result<()> {
+    match path
+        .extension()
+        .unwrap_or_default()
+        .to_str()
+        .unwrap_or_default()
+        .to_lowercase()
+        .as_str()
+    {
+        "qoi" => {
+            let encoded = qoi::encode_to_vec(img.as_raw(), img.width(), img.height())?;
+            std::fs::write(path, encoded)?;
+        }
+        _ => img.save(path)?,
+    }
+    Ok(())
+}
+
 pub fn clipboard_copy(img: &RgbaImage) {
     if let Ok(clipboard) = &mut Clipboard::new() {
         let _ = clipboard.set_image(arboard::ImageData {
@@ -0,0 +1,248 @@
+use log::{debug, error};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::navigation::{list_folder, SortOrder};
+use crate::utils::is_ext_compatible;
+
+/// How long a file must stay unchanged before it is considered completely written
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// How many images the watch folder mode remembers
+const HISTORY_LEN: usize = 20;
+
+/// How often a window without input looks for changed files
+pub const IDLE_POLL: Duration = Duration::from_millis(100);
+
+/// How long after the last input a window counts as idle
+const IDLE_AFTER: Duration = Duration::from_secs(1);
+
+/// A file that changed recently
+#[derive(Debug)]
+struct Pending {
+    last_change: Instant,
+    size: Option<u64>,
+}
+
+/// Watches a folder and reports files once they stopped changing
+#[derive(Debug, Default)]
+pub struct FolderWatcher {
+    watcher: Option<RecommendedWatcher>,
+    /// Events with the time they happened
+    receiver: Option<Receiver<(Instant, notify::Result<Event>)>>,
+    dir: Option<PathBuf>,
+    pending: HashMap<PathBuf, Pending>,
+}
+
+impl FolderWatcher {
+    /// Watch `dir` instead of the previous folder
+    pub fn watch(&mut self, dir: &Path) {
+        if self.dir.as_deref() == Some(dir) {
+            return;
+        }
+        self.stop();
+        let (sender, receiver) = mpsc::channel();
+        let handler = move |event| _ = sender.send((Instant::now(), event));
+        let watcher = notify::recommended_watcher(handler).and_then(|mut watcher| {
+            watcher.watch(dir, RecursiveMode::NonRecursive)?;
+            Ok(watcher)
+        });
+        match watcher {
+            Ok(watcher) => {
+                debug!("Watching {}", dir.display());
+                self.watcher = Some(watcher);
+                self.receiver = Some(receiver);
+                self.dir = Some(dir.to_path_buf());
+            }
+            Err(e) => error!("Can't watch {}: {e}", dir.display()),
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.watcher = None;
+        self.receiver = None;
+        self.dir = None;
+        self.pending.clear();
+    }
+
+    /// The folder being watched
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    /// Files that changed and have not been written to for a while.
+    /// Removed files are reported too, so the folder listing can be updated.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        if let Some(receiver) = &self.receiver {
+            for (time, event) in receiver.try_iter() {
+                match event {
+                    Ok(event) => {
+                        if matches!(event.kind, EventKind::Access(_)) {
+                            continue;
+                        }
+                        for path in event.paths {
+                            self.pending.insert(
+                                path.clone(),
+                                Pending {
+                                    last_change: time,
+                                    size: file_size(&path),
+                                },
+                            );
+                        }
+                    }
+                    Err(e) => error!("Watch error: {e}"),
+                }
+            }
+        }
+
+        let mut settled = vec![];
+        for (path, pending) in &mut self.pending {
+            if pending.last_change.elapsed() < DEBOUNCE {
+                continue;
+            }
+            // a file that still grows is being written, wait for it to finish
+            let size = file_size(path);
+            if size != pending.size {
+                pending.size = size;
+                pending.last_change = Instant::now();
+                continue;
+            }
+            settled.push(path.clone());
+        }
+        for path in &settled {
+            self.pending.remove(path);
+        }
+        settled
+    }
+}
+
+/// How long to wait before the next frame while folders are watched.
+/// File changes don't wake up the event loop, so an idle window keeps drawing slowly to find them.
+pub fn idle_wait(last_input: Instant) -> Duration {
+    if last_input.elapsed() < IDLE_AFTER {
+        Duration::ZERO
+    } else {
+        IDLE_POLL
+    }
+}
+
+fn file_size(path: &Path) -> Option<u64> {
+    std::fs::metadata(path).map(|m| m.len()).ok()
+}
+
+/// Shows the newest image of a folder as soon as it is written
+#[derive(Debug, Default)]
+pub struct WatchFolder {
+    pub active: bool,
+    watcher: FolderWatcher,
+    /// Recently shown images, oldest first
+    history: VecDeque<PathBuf>,
+    /// Position in the history while flipping back. `None` follows new images.
+    position: Option<usize>,
+}
+
+impl WatchFolder {
+    /// Start watching `dir`. Returns its newest image, if there is one.
+    pub fn start(&mut self, dir: &Path) -> Option<PathBuf> {
+        self.watcher.watch(dir);
+        self.active = self.watcher.dir().is_some();
+        self.history.clear();
+        self.position = None;
+        let newest = list_folder(dir, SortOrder::Modified, true, &Default::default(), 0)
+            .into_iter()
+            .next()?;
+        self.remember(&newest);
+        Some(newest)
+    }
+
+    pub fn stop(&mut self) {
+        self.active = false;
+        self.watcher.stop();
+        self.history.clear();
+        self.position = None;
+    }
+
+    pub fn dir(&self) -> Option<&Path> {
+        self.watcher.dir()
+    }
+
+    /// Whether new images are shown as they arrive
+    pub fn is_following(&self) -> bool {
+        self.position.is_none()
+    }
+
+    /// The shown position in the history and its length
+    pub fn history_position(&self) -> (usize, usize) {
+        (
+            self.position
+                .unwrap_or(self.history.len().saturating_sub(1)),
+            self.history.len(),
+        )
+    }
+
+    fn remember(&mut self, path: &Path) {
+        self.history.retain(|p| p != path);
+        self.history.push_back(path.to_path_buf());
+        while self.history.len() > HISTORY_LEN {
+            self.history.pop_front();
+            self.position = self.position.map(|p| p.saturating_sub(1));
+        }
+    }
+
+    /// Check for new images. Returns the newest one once it is completely written,
+    /// unless an older image from the history is being looked at.
+    pub fn poll(&mut self) -> Option<PathBuf> {
+        let mut arrived = self
+            .watcher
+            .poll()
+            .into_iter()
+            .filter(|p| p.is_file() && is_ext_compatible(p))
+            .map(|p| {
+                let modified = std::fs::metadata(&p)
+                    .and_then(|m| m.modified())
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+                (modified, p)
+            })
+            .collect::<Vec<_>>();
+        arrived.sort();
+        for (_, path) in &arrived {
+            self.remember(path);
+        }
+        let (_, newest) = arrived.pop()?;
+        if self.is_following() {
+            Some(newest)
+        } else {
+            None
+        }
+    }
+
+    /// Go to an older image in the history
+    pub fn back(&mut self) -> Option<PathBuf> {
+        let current = self.history_position().0;
+        if current == 0 {
+            return None;
+        }
+        self.position = Some(current - 1);
+        self.history.get(current - 1).cloned()
+    }
+
+    /// Go to a newer image in the history, following new images again at the end
+    pub fn forward(&mut self) -> Option<PathBuf> {
+        let next = self.position? + 1;
+        if next + 1 >= self.history.len() {
+            self.position = None;
+        } else {
+            self.position = Some(next);
+        }
+        self.history.get(next).cloned()
+    }
+
+    /// Jump to the newest image and follow new ones again
+    pub fn follow(&mut self) -> Option<PathBuf> {
+        self.position = None;
+        self.history.back().cloned()
+    }
+}
@@ -0,0 +1,274 @@
+use anyhow::{anyhow, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use strum::Display;
+use strum_macros::EnumIter;
+
+const XMP_NS: &str = "http://ns.adobe.com/xap/1.0/";
+const DC_NS: &str = "http://purl.org/dc/elements/1.1/";
+
+/// What a new sidecar starts out as
+const EMPTY_SIDECAR: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""/>
+ </rdf:RDF>
+</x:xmpmeta>
+"#;
+
+/// Color labels as written to `xmp:Label`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumIter, Display)]
+pub enum ColorLabel {
+    Red,
+    Yellow,
+    Green,
+    Blue,
+    Purple,
+}
+
+impl ColorLabel {
+    pub fn rgb(&self) -> [u8; 3] {
+        match self {
+            ColorLabel::Red => [230, 60, 60],
+            ColorLabel::Yellow => [235, 200, 40],
+            ColorLabel::Green => [70, 190, 80],
+            ColorLabel::Blue => [60, 120, 230],
+            ColorLabel::Purple => [160, 80, 200],
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        use strum::IntoEnumIterator;
+        ColorLabel::iter().find(|l| l.to_string().eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+/// Culling information of an image
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct XmpData {
+    /// Stars from 0 to 5, where 0 is unrated. Rejected images have -1.
+    pub rating: i8,
+    pub label: Option<ColorLabel>,
+    pub tags: Vec<String>,
+}
+
+/// The sidecar of `image`. darktable and digiKam name it `image.jpg.xmp`,
+/// other tools use `image.xmp`, which is read if it is the only one.
+pub fn sidecar_path(image: &Path) -> PathBuf {
+    let mut full = image.as_os_str().to_owned();
+    full.push(".xmp");
+    let full = PathBuf::from(full);
+    let short = image.with_extension("xmp");
+    if !full.exists() && short.exists() {
+        short
+    } else {
+        full
+    }
+}
+
+/// Read the rating, label and tags of `image`. Images without sidecar have none.
+pub fn read(image: &Path) -> XmpData {
+    match std::fs::read_to_string(sidecar_path(image)) {
+        Ok(xml) => parse(&xml),
+        Err(_) => XmpData::default(),
+    }
+}
+
+/// Store rating, label and tags in the sidecar of `image`, keeping everything else in it
+pub fn write(image: &Path, data: &XmpData) -> Result<()> {
+    let path = sidecar_path(image);
+    let xml = std::fs::read_to_string(&path).unwrap_or_else(|_| EMPTY_SIDECAR.to_string());
+    std::fs::write(&path, update(&xml, data)?)?;
+    debug!("Wrote {}", path.display());
+    Ok(())
+}
+
+pub fn parse(xml: &str) -> XmpData {
+    let property = |name: &str| attribute(xml, name).or_else(|| element_text(xml, name));
+    let tags = match (xml.find("<dc:subject>"), xml.find("</dc:subject>")) {
+        (Some(start), Some(end)) if start < end => list_items(&xml[start..end]),
+        _ => vec![],
+    };
+    XmpData {
+        rating: property("xmp:Rating")
+            .and_then(|r| r.trim().parse::<i32>().ok())
+            .unwrap_or_default()
+            .clamp(-1, 5) as i8,
+        label: property("xmp:Label").and_then(|l| ColorLabel::parse(&l)),
+        tags,
+    }
+}
+
+/// Put `data` into an xmp document. Only the rating, label and subject are touched.
+pub fn update(xml: &str, data: &XmpData) -> Result<String> {
+    let mut xml = xml.to_string();
+    for name in ["xmp:Rating", "xmp:Label", "dc:subject"] {
+        xml = remove_element(&xml, name);
+    }
+
+    let start = xml
+        .find("<rdf:Description")
+        .ok_or(anyhow!("Sidecar contains no rdf:Description"))?;
+    let end = tag_end(&xml, start).ok_or(anyhow!("Sidecar is not valid xml"))?;
+    let mut tag = xml[start..end].to_string();
+    for name in ["xmp:Rating", "xmp:Label"] {
+        tag = remove_attribute(&tag, name);
+    }
+
+    let self_closing = tag.ends_with("/>");
+    let mut new_tag = tag
+        .trim_end_matches('>')
+        .trim_end_matches('/')
+        .trim_end()
+        .to_string();
+    if !xml.contains("xmlns:xmp=") {
+        new_tag.push_str(&format!("\n    xmlns:xmp=\"{XMP_NS}\""));
+    }
+    if !xml.contains("xmlns:dc=") && !data.tags.is_empty() {
+        new_tag.push_str(&format!("\n    xmlns:dc=\"{DC_NS}\""));
+    }
+    new_tag.push_str(&format!("\n   xmp:Rating=\"{}\"", data.rating));
+    if let Some(label) = data.label {
+        new_tag.push_str(&format!("\n   xmp:Label=\"{label}\""));
+    }
+    new_tag.push('>');
+    if !data.tags.is_empty() {
+        new_tag.push_str("\n   <dc:subject>\n    <rdf:Bag>\n");
+        for tag in &data.tags {
+            new_tag.push_str(&format!("     <rdf:li>{}</rdf:li>\n", escape(tag)));
+        }
+        new_tag.push_str("    </rdf:Bag>\n   </dc:subject>");
+    }
+    if self_closing {
+        new_tag.push_str("\n  </rdf:Description>");
+    }
+
+    xml.replace_range(start..end, &new_tag);
+    Ok(xml)
+}
+
+/// Index after the `>` closing the tag that starts at `start`
+fn tag_end(xml: &str, start: usize) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in xml[start..].char_indices() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('>', None) => return Some(start + i + 1),
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Position of an attribute including the whitespace before it, and its value
+fn find_attribute(xml: &str, name: &str) -> Option<(usize, usize, String)> {
+    let mut from = 0;
+    while let Some(pos) = xml[from..].find(name).map(|p| p + from) {
+        from = pos + name.len();
+        let preceded_by_space = xml[..pos]
+            .chars()
+            .last()
+            .map(|c| c.is_whitespace())
+            .unwrap_or_default();
+        let rest = xml[from..].trim_start();
+        if !preceded_by_space || !rest.starts_with('=') {
+            continue;
+        }
+        let value = rest[1..].trim_start();
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let value_start = xml.len() - value.len() + 1;
+        let value_end = xml[value_start..].find(quote)? + value_start;
+        let start = xml[..pos].trim_end().len();
+        return Some((start, value_end + 1, unescape(&xml[value_start..value_end])));
+    }
+    None
+}
+
+fn attribute(xml: &str, name: &str) -> Option<String> {
+    find_attribute(xml, name).map(|(_, _, value)| value)
+}
+
+fn remove_attribute(xml: &str, name: &str) -> String {
+    let mut xml = xml.to_string();
+    while let Some((start, end, _)) = find_attribute(&xml, name) {
+        xml.replace_range(start..end, "");
+    }
+    xml
+}
+
+/// Range of the element `name`, whether it has content or is self closing
+fn find_element(xml: &str, name: &str) -> Option<(usize, usize)> {
+    let open = format!("<{name}");
+    let mut from = 0;
+    while let Some(start) = xml[from..].find(&open).map(|p| p + from) {
+        from = start + open.len();
+        // make sure this is not just an element with a longer name
+        match xml[from..].chars().next() {
+            Some(c) if c == '>' || c == '/' || c.is_whitespace() => (),
+            _ => continue,
+        }
+        let end = tag_end(xml, start)?;
+        if xml[..end].ends_with("/>") {
+            return Some((start, end));
+        }
+        let close = format!("</{name}>");
+        let close_start = xml[end..].find(&close)? + end;
+        return Some((start, close_start + close.len()));
+    }
+    None
+}
+
+fn element_text(xml: &str, name: &str) -> Option<String> {
+    let (start, end) = find_element(xml, name)?;
+    let element = &xml[start..end];
+    let content_start = tag_end(element, 0)?;
+    let content_end = element.rfind("</")?;
+    (content_start <= content_end).then(|| unescape(&element[content_start..content_end]))
+}
+
+fn remove_element(xml: &str, name: &str) -> String {
+    let mut xml = xml.to_string();
+    while let Some((start, end)) = find_element(&xml, name) {
+        // take the indentation along
+        let start = xml[..start].trim_end_matches([' ', '\t']).len();
+        let start = xml[..start]
+            .strip_suffix('\n')
+            .map(|s| s.len())
+            .unwrap_or(start);
+        xml.replace_range(start..end, "");
+    }
+    xml
+}
+
+/// Texts of all `rdf:li` items
+fn list_items(xml: &str) -> Vec<String> {
+    let mut items = vec![];
+    let mut rest = xml;
+    while let Some((start, end)) = find_element(rest, "rdf:li") {
+        if let Some(text) = element_text(&rest[start..end], "rdf:li") {
+            if !text.trim().is_empty() {
+                items.push(text.trim().to_string());
+            }
+        }
+        rest = &rest[end..];
+    }
+    items
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,86 @@
+use nalgebra::Vector2;
+use serde::{Deserialize, Serialize};
+use strum::Display;
+use strum_macros::EnumIter;
+
+use crate::orientation;
+use crate::utils::{ImageExt, OculanteState};
+
+/// Smallest and largest zoom factor
+pub const MIN_SCALE: f32 = 0.05;
+pub const MAX_SCALE: f32 = 40.0;
+
+/// Zoom factors that can be chosen directly
+pub const ZOOM_LEVELS: [f32; 4] = [1.0, 2.0, 4.0, 8.0];
+
+/// How an image is scaled to the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumIter, Display)]
+pub enum FitMode {
+    /// Fit the window, but never enlarge small images
+    #[strum(serialize = "Fit (shrink only)")]
+    Shrink,
+    Fit,
+    #[strum(serialize = "Fit width")]
+    Width,
+    #[strum(serialize = "Fit height")]
+    Height,
+    /// Cover the whole window, cutting off the edges
+    Fill,
+    #[strum(serialize = "100%")]
+    Actual,
+}
+
+impl Default for FitMode {
+    fn default() -> Self {
+        FitMode::Shrink
+    }
+}
+
+impl FitMode {
+    /// Scale showing content of `size` in `window` this way
+    pub fn scale(&self, size: Vector2<f32>, window: Vector2<f32>) -> f32 {
+        let (x, y) = (window.x / size.x, window.y / size.y);
+        let scale = match self {
+            FitMode::Shrink => x.min(y).min(1.0),
+            FitMode::Fit => x.min(y),
+            FitMode::Width => x,
+            FitMode::Height => y,
+            FitMode::Fill => x.max(y),
+            FitMode::Actual => 1.0,
+        };
+        // huge images still fit completely, even below the smallest manual zoom
+        scale.min(MAX_SCALE)
+    }
+}
+
+/// Change the zoom, keeping the point under `center` in place
+pub fn zoom_to(state: &mut OculanteState, scale: f32, center: Vector2<f32>) {
+    // a fitted huge image may already be below the smallest zoom, zooming out keeps it there
+    let scale = scale.clamp(MIN_SCALE.min(state.scale), MAX_SCALE);
+    state.offset = center - (center - state.offset) * (scale / state.scale);
+    state.scale = scale;
+}
+
+/// Change the zoom around the middle of the window
+pub fn zoom_centered(state: &mut OculanteState, scale: f32) {
+    let center = state.window_size / 2.0;
+    zoom_to(state, scale, center);
+}
+
+/// Scale the image with `mode` and center it, as it is rotated.
+/// Both images are fitted while comparing.
+pub fn fit(state: &mut OculanteState, mode: FitMode) {
+    let first = match &state.current_image {
+        Some(img) => img.size_vec(),
+        None => return,
+    };
+    let (origin, size) = match state.compare.image {
+        Some(_) => state.compare.bounds(first),
+        None => (
+            Vector2::zeros(),
+            orientation::current(state).view_size(first),
+        ),
+    };
+    state.scale = mode.scale(size, state.window_size);
+    state.offset = state.window_size / 2.0 - (size / 2.0 + origin) * state.scale;
+}